@@ -1,21 +1,99 @@
 use serde::Deserialize;
+use shark::region::Region;
 use std::fs::File;
 use std::io::Read;
 use std::net::IpAddr;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+/// Either the name of a built in region or the endpoints of a custom Ayla deployment
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum SharkRegion {
+    Name(String),
+    Custom {
+        user_url: String,
+        device_url: String,
+        app_id: String,
+        app_secret: String,
+    },
+}
+
+impl SharkRegion {
+    pub fn to_region(&self) -> Result<Region, String> {
+        match self {
+            Self::Name(name) => name.parse(),
+            Self::Custom {
+                user_url,
+                device_url,
+                app_id,
+                app_secret,
+            } => Ok(Region::Custom {
+                user_url: user_url.clone(),
+                device_url: device_url.clone(),
+                app_id: app_id.clone(),
+                app_secret: app_secret.clone(),
+            }),
+        }
+    }
+}
 
 #[derive(Deserialize)]
 pub struct SharkAuth {
+    /// Tells accounts apart in logs, defaults to `user`
+    pub name: Option<String>,
     pub user: String,
     pub password: String,
+    /// Defaults to the US region
+    pub region: Option<SharkRegion>,
+    /// File used to persist session tokens across restarts
+    pub token_file: Option<PathBuf>,
+    /// Seconds between polls of each device for state changes, defaults to 60
+    pub watch_interval: Option<u64>,
+    /// Seconds that device lists and properties are reused, defaults to 30, 0 disables caching
+    pub cache_ttl: Option<u64>,
+    /// Seconds before a request to Shark times out, defaults to 30
+    pub timeout: Option<u64>,
+    /// Stay signed in on shutdown so the session in `token_file` can be reused by the next
+    /// start, defaults to whether a `token_file` is set. Otherwise the account is signed out and
+    /// its token file cleared.
+    pub keep_session: Option<bool>,
+}
+
+impl SharkAuth {
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.user)
+    }
+
+    pub fn keep_session(&self) -> bool {
+        self.keep_session.unwrap_or(self.token_file.is_some())
+    }
+}
+
+/// Either a single `[shark]` account or a list of `[[shark]]` accounts
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum SharkConfig {
+    Single(Box<SharkAuth>),
+    Multiple(Vec<SharkAuth>),
+}
+
+impl SharkConfig {
+    pub fn accounts(&self) -> &[SharkAuth] {
+        match self {
+            Self::Single(account) => std::slice::from_ref(account.as_ref()),
+            Self::Multiple(accounts) => accounts,
+        }
+    }
 }
 
 #[derive(Deserialize)]
 pub struct Config {
     pub user_auth: Vec<String>,
-    pub shark: SharkAuth,
+    pub shark: SharkConfig,
     pub host: Option<IpAddr>,
     pub port: Option<u16>,
+    /// Seconds to wait for in-flight requests when shutting down, defaults to 30
+    pub drain_timeout: Option<u64>,
 }
 
 impl Config {
@@ -1,22 +1,24 @@
 use anyhow::anyhow;
-use dropshot::{
-    ApiDescription, ConfigDropshot, ConfigLogging, ConfigLoggingLevel, HttpError,
-    HttpServerStarter, RequestInfo,
-};
+use dropshot::{ApiDescription, ConfigDropshot, HttpError, HttpServerStarter, RequestInfo};
 use hyper::StatusCode;
 use illumos_priv::{PrivOp, PrivPtype, PrivSet, Privilege};
-use shark::SharkClient;
+use shark::SharkEvent;
+use shark_accounts::SharkAccounts;
+use slog::{Drain, Level, LevelFilter, Logger};
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::RwLock;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::broadcast;
 use tokio::time;
 
 #[macro_use]
 extern crate slog;
 
 mod config;
+mod shark_accounts;
 mod shark_endpoint;
+mod shark_watch;
 mod sonos_endpoint;
 
 const X_API_KEY: &str = "X-API-Key";
@@ -26,7 +28,9 @@ pub struct Auth;
 
 type AppCtx = Arc<App>;
 pub struct App {
-    shark: RwLock<SharkClient>,
+    shark: SharkAccounts,
+    /// State changes of every shark device, subscribe to be notified of them
+    shark_events: broadcast::Sender<SharkEvent>,
     auth_tokens: Vec<String>,
 }
 
@@ -54,6 +58,16 @@ impl App {
     }
 }
 
+/// Wait for SIGINT or SIGTERM and return the name of the signal received
+async fn shutdown_signal() -> std::io::Result<&'static str> {
+    let mut sigint = signal(SignalKind::interrupt())?;
+    let mut sigterm = signal(SignalKind::terminate())?;
+    tokio::select! {
+        _ = sigint.recv() => Ok("SIGINT"),
+        _ = sigterm.recv() => Ok("SIGTERM"),
+    }
+}
+
 fn drop_privs() -> anyhow::Result<()> {
     let mut pset = PrivSet::new_basic()?;
     pset.delset(Privilege::ProcFork)?;
@@ -84,23 +98,26 @@ async fn main() -> anyhow::Result<()> {
     let host = config.host.unwrap_or_else(|| "127.0.0.1".parse().unwrap());
     let port = config.port.unwrap_or(8080);
     let sa = SocketAddr::new(host, port);
+    let drain_timeout = Duration::from_secs(config.drain_timeout.unwrap_or(30));
 
-    let shark = SharkClient::builder(&config.shark.user, &config.shark.password)
-        .build()
-        .await
-        .map_err(|e| anyhow!("failed to create shark client: {}", e))?;
+    let shark = SharkAccounts::from_config(config.shark.accounts()).await?;
 
-    let app = Arc::new(App {
-        shark: RwLock::new(shark),
+    let (shark_events, _) = broadcast::channel(64);
+    let appctx = Arc::new(App {
+        shark,
+        shark_events,
         auth_tokens: config.user_auth,
     });
-    let appctx = Arc::clone(&app);
+    let app = Arc::clone(&appctx);
 
-    let log = ConfigLogging::StderrTerminal {
-        level: ConfigLoggingLevel::Info,
-    }
-    .to_logger("home-api")
-    .map_err(|e| anyhow!("{}", e))?;
+    // Like dropshot's stderr logger, but keeping the guard so queued messages are written before
+    // exiting even while clones of the logger are still around
+    let decorator = slog_term::TermDecorator::new().build();
+    let drain = slog_term::FullFormat::new(decorator).build().fuse();
+    let drain = LevelFilter::new(drain, Level::Info).fuse();
+    let (drain, log_guard) = slog_async::Async::new(drain).build_with_guard();
+    // Anything still logging after the guard is dropped is discarded
+    let log = Logger::root(drain.ignore_res(), o!("name" => "home-api"));
 
     let mut api = ApiDescription::new();
     sonos_endpoint::mount(&mut api);
@@ -120,20 +137,62 @@ async fn main() -> anyhow::Result<()> {
 
     drop_privs().map_err(|e| anyhow!("Failed to drop privs: {}", e))?;
 
-    tokio::task::spawn(async move {
-        let mut interval = time::interval(Duration::from_secs(60 * 60 * 12));
-        interval.tick().await;
+    let mut tasks = vec![tokio::task::spawn(shark_watch::log_events(
+        Arc::clone(&app),
+        log.clone(),
+    ))];
+    for account in app.shark.iter() {
+        tasks.push(tokio::task::spawn(shark_watch::watch_devices(
+            Arc::clone(account),
+            app.shark_events.clone(),
+            log.new(o!("shark_account" => account.name.clone())),
+        )));
+    }
 
-        loop {
-            interval.tick().await;
-            let mut shark = app.shark.write().await;
-            match shark.refresh_token().await {
-                Ok(_) => info!(&log, "refreshed shark access_token"),
-                Err(e) => error!(&log, "error refreshing shark token: {}", e),
+    let mut server = server.start();
+    tokio::select! {
+        result = &mut server => return result.map_err(|e| anyhow!("{}", e)),
+        signal = shutdown_signal() => {
+            let signal = signal.map_err(|e| anyhow!("failed to wait for signals: {}", e))?;
+            info!(&log, "received {}, shutting down", signal);
+        }
+    }
+
+    // Stop polling devices before the shark sessions go away
+    for task in tasks {
+        task.abort();
+        let _ = task.await;
+    }
+
+    let drained = match time::timeout(drain_timeout, server.close()).await {
+        Ok(Ok(())) => true,
+        Ok(Err(e)) => {
+            error!(&log, "failed to stop server: {}", e);
+            true
+        }
+        Err(_) => {
+            warn!(
+                &log,
+                "in-flight requests did not finish within {:?}, not signing out of shark",
+                drain_timeout
+            );
+            false
+        }
+    };
+
+    // Handlers still running may be using the sessions
+    if drained {
+        for account in app.shark.iter().filter(|a| !a.keep_session) {
+            if let Err(e) = account.client.sign_out().await {
+                warn!(
+                    &log,
+                    "failed to sign out of shark account {}: {}", account.name, e
+                );
             }
         }
-    });
+    }
 
-    let server_task = server.start();
-    server_task.await.map_err(|e| anyhow!("{}", e))
+    info!(&log, "shutdown complete");
+    drop(log_guard);
+    Ok(())
 }
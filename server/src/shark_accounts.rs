@@ -0,0 +1,180 @@
+use crate::config::SharkAuth;
+use anyhow::anyhow;
+use futures::future;
+use shark::{FileTokenStore, SharkClient, SharkDevice, SharkError};
+use slog::Logger;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A Shark account from the config file
+pub struct SharkAccount {
+    pub name: String,
+    pub client: SharkClient,
+    /// Time between polls of the account's devices for state changes
+    pub watch_interval: Duration,
+    /// Stay signed in on shutdown
+    pub keep_session: bool,
+}
+
+impl SharkAccount {
+    async fn from_config(config: &SharkAuth) -> anyhow::Result<Self> {
+        let name = config.name().to_string();
+        let mut shark = SharkClient::builder(&config.user, &config.password);
+        if let Some(region) = &config.region {
+            let region = region
+                .to_region()
+                .map_err(|e| anyhow!("Failed to parse config file: {}", e))?;
+            shark = shark.region(region);
+        }
+        if let Some(token_file) = &config.token_file {
+            shark = shark.token_store(FileTokenStore::new(token_file));
+        }
+        if let Some(timeout) = config.timeout {
+            shark = shark.timeout(Duration::from_secs(timeout));
+        }
+        if let Some(cache_ttl) = config.cache_ttl {
+            shark = shark.cache_ttl(Duration::from_secs(cache_ttl));
+        }
+        let client = shark
+            .build()
+            .await
+            .map_err(|e| anyhow!("failed to create shark client for account {}: {}", name, e))?;
+
+        Ok(Self {
+            name,
+            client,
+            watch_interval: Duration::from_secs(config.watch_interval.unwrap_or(60)),
+            keep_session: config.keep_session(),
+        })
+    }
+
+    async fn list_devices(&self, refresh: bool) -> Result<Vec<SharkDevice>, SharkError> {
+        if refresh {
+            self.client.refresh_devices().await
+        } else {
+            self.client.get_devices().await
+        }
+    }
+}
+
+/// Every configured Shark account. Devices are listed across all accounts and actions on a
+/// device are routed to the account that owns it.
+pub struct SharkAccounts {
+    accounts: Vec<Arc<SharkAccount>>,
+}
+
+impl SharkAccounts {
+    pub async fn from_config(config: &[SharkAuth]) -> anyhow::Result<Self> {
+        let mut accounts: Vec<Arc<SharkAccount>> = Vec::with_capacity(config.len());
+        for account in config {
+            if accounts.iter().any(|a| a.name == account.name()) {
+                return Err(anyhow!(
+                    "Failed to parse config file: duplicate shark account {}",
+                    account.name()
+                ));
+            }
+            accounts.push(Arc::new(SharkAccount::from_config(account).await?));
+        }
+
+        Ok(Self { accounts })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<SharkAccount>> {
+        self.accounts.iter()
+    }
+
+    /// List the devices of every account, bypassing the device cache when `refresh` is set.
+    /// Accounts that fail to list their devices are logged and left out, unless all of them fail.
+    pub async fn get_devices(
+        &self,
+        refresh: bool,
+        log: &Logger,
+    ) -> Result<Vec<SharkDevice>, SharkError> {
+        let results = future::join_all(
+            self.accounts
+                .iter()
+                .map(|account| async move { (account, account.list_devices(refresh).await) }),
+        )
+        .await;
+
+        let mut devices = Vec::new();
+        let mut failure = None;
+        for (account, result) in results {
+            match result {
+                Ok(d) => devices.push(d),
+                Err(e) => {
+                    warn!(
+                        log,
+                        "failed to list devices of shark account {}: {}", account.name, e
+                    );
+                    failure = Some(e);
+                }
+            }
+        }
+
+        match failure {
+            Some(e) if devices.is_empty() => Err(e),
+            _ => Ok(devices.into_iter().flatten().collect()),
+        }
+    }
+
+    /// Find the account owning the device identified by `name`, which is either its DSN or its
+    /// user-assigned name, and return its client along with the device's DSN.
+    ///
+    /// Names are looked up in each account's recently fetched device list first. Only if no
+    /// account knows the name are the lists fetched again.
+    pub async fn resolve_device(&self, name: &str) -> Result<(&SharkClient, String), SharkError> {
+        match self.find_device(name, false).await {
+            Err(SharkError::UnknownDevice { .. }) => self.find_device(name, true).await,
+            res => res,
+        }
+    }
+
+    async fn find_device(
+        &self,
+        name: &str,
+        refresh: bool,
+    ) -> Result<(&SharkClient, String), SharkError> {
+        let mut found = Vec::new();
+        let mut ambiguous = Vec::new();
+        let mut suggestions = Vec::new();
+        let mut failure = None;
+
+        for account in &self.accounts {
+            let devices = match account.list_devices(refresh).await {
+                Ok(devices) => devices,
+                // The device may still be found in another account
+                Err(e) => {
+                    failure = Some(e);
+                    continue;
+                }
+            };
+            match shark::find_device(&devices, name) {
+                Ok(dsn) => found.push((&account.client, dsn)),
+                Err(SharkError::AmbiguousDevice { dsns, .. }) => ambiguous.extend(dsns),
+                Err(SharkError::UnknownDevice { suggestions: s, .. }) => suggestions.extend(s),
+                Err(e) => return Err(e),
+            }
+        }
+
+        if found.len() == 1 && ambiguous.is_empty() {
+            return Ok(found.remove(0));
+        }
+        if found.is_empty() && ambiguous.is_empty() {
+            // The device may belong to the account that couldn't be asked
+            if let Some(e) = failure {
+                return Err(e);
+            }
+            return Err(SharkError::UnknownDevice {
+                name: name.to_string(),
+                suggestions,
+            });
+        }
+
+        ambiguous.extend(found.into_iter().map(|(_, dsn)| dsn));
+        Err(SharkError::AmbiguousDevice {
+            name: name.to_string(),
+            dsns: ambiguous,
+        })
+    }
+}
@@ -1,14 +1,112 @@
+use crate::shark_accounts::SharkAccounts;
 use crate::AppCtx;
+use chrono::{DateTime, Utc};
 use dropshot::{
-    endpoint, ApiDescription, HttpError, HttpResponseAccepted, HttpResponseOk, Path, RequestContext,
+    endpoint, ApiDescription, HttpError, HttpResponseAccepted, HttpResponseOk, Path, Query,
+    RequestContext, TypedBody,
 };
+use hyper::StatusCode;
 use schemars::JsonSchema;
 use serde::Deserialize;
-use shark::SharkDevice;
+use shark::{
+    Datapoint, OperatingMode, PowerMode, SharkClient, SharkDevice, SharkDeviceStatus, SharkError,
+    SharkMaintenance,
+};
 
 #[derive(Deserialize, JsonSchema)]
 struct ActionPathParam {
+    /// The device's DSN or its user-assigned name
+    dsn: String,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct PropertyPathParam {
+    /// The device's DSN or its user-assigned name
     dsn: String,
+    /// The Ayla property name, e.g. `GET_Battery_Capacity`
+    name: String,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct DevicesQueryArgs {
+    /// Only return devices that are (or aren't) connected to the Ayla cloud
+    online: Option<bool>,
+    /// Fetch the device list from the Ayla cloud instead of reusing a cached one
+    #[serde(default)]
+    refresh: bool,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct HistoryQueryArgs {
+    /// Only return values reported since this time
+    since: Option<DateTime<Utc>>,
+    /// Return at most this many values
+    limit: Option<u32>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+enum ModeAction {
+    Start,
+    Stop,
+    /// Pause a running clean
+    Pause,
+    /// Continue a paused clean
+    Resume,
+    /// Return to the dock
+    Return,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct ModeArgs {
+    mode: ModeAction,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct PowerArgs {
+    mode: PowerMode,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct CleanArgs {
+    rooms: Vec<String>,
+}
+
+/// Map a shark client error to the HTTP status that best describes it
+fn shark_error(e: SharkError) -> HttpError {
+    let message = format!("shark api error: {}", e);
+    match e.api_error().map(|api| api.status) {
+        Some(StatusCode::NOT_FOUND) => HttpError::for_not_found(None, message),
+        Some(StatusCode::TOO_MANY_REQUESTS) => {
+            HttpError::for_client_error(None, StatusCode::TOO_MANY_REQUESTS, message)
+        }
+        _ if e.is_auth_error() => {
+            HttpError::for_client_error(None, StatusCode::UNAUTHORIZED, message)
+        }
+        _ if e.is_retryable() => HttpError::for_unavail(None, message),
+        _ => match e {
+            SharkError::UnknownRoom(_) | SharkError::InvalidPropertyName(_) => {
+                HttpError::for_bad_request(None, message)
+            }
+            SharkError::InvalidState(_) => {
+                HttpError::for_client_error(None, StatusCode::CONFLICT, message)
+            }
+            // Unlike `for_not_found` this passes the suggested names on to the caller
+            SharkError::UnknownDevice { .. } | SharkError::AmbiguousDevice { .. } => {
+                HttpError::for_client_error(None, StatusCode::NOT_FOUND, message)
+            }
+            _ => HttpError::for_internal_error(message),
+        },
+    }
+}
+
+/// Resolve a `{dsn}` path parameter, which may also be the device's user-assigned name, to the
+/// client of the account owning the device and the device's DSN
+async fn resolve_dsn<'a>(
+    accounts: &'a SharkAccounts,
+    dsn: &str,
+) -> Result<(&'a SharkClient, String), HttpError> {
+    accounts.resolve_device(dsn).await.map_err(shark_error)
 }
 
 #[endpoint {
@@ -17,44 +115,175 @@ struct ActionPathParam {
 }]
 async fn get_devices(
     rctx: RequestContext<AppCtx>,
+    query: Query<DevicesQueryArgs>,
 ) -> Result<HttpResponseOk<Vec<SharkDevice>>, HttpError> {
     let app = rctx.context();
     let req = &rctx.request;
     let _ = app.require_auth(&req)?;
+    let query = query.into_inner();
 
-    let shark = app.shark.read().await;
-    match shark.get_devices().await {
-        Ok(devices) => Ok(HttpResponseOk(devices)),
-        Err(e) => Err(HttpError::for_internal_error(format!(
-            "shark api error: {}",
-            e
-        ))),
+    match app.shark.get_devices(query.refresh, &rctx.log).await {
+        Ok(devices) => Ok(HttpResponseOk(
+            devices
+                .into_iter()
+                .filter(|d| match query.online {
+                    Some(online) => d.is_online() == online,
+                    None => true,
+                })
+                .collect(),
+        )),
+        Err(e) => Err(shark_error(e)),
     }
 }
 
 #[endpoint {
-    method = PUT,
-    path = "/shark/devices/{dsn}/start",
+    method = GET,
+    path = "/shark/devices/{dsn}",
 }]
-async fn start(
+async fn get_device(
     rctx: RequestContext<AppCtx>,
     path_params: Path<ActionPathParam>,
-) -> Result<HttpResponseAccepted<()>, HttpError> {
+) -> Result<HttpResponseOk<SharkDevice>, HttpError> {
     let app = rctx.context();
     let req = &rctx.request;
     let _ = app.require_auth(&req)?;
-    let shark = app.shark.read().await;
-    let dsn = path_params.into_inner().dsn;
+    let (shark, dsn) = resolve_dsn(&app.shark, &path_params.into_inner().dsn).await?;
+
+    match shark.get_device(&dsn).await {
+        Ok(device) => Ok(HttpResponseOk(device)),
+        Err(e) => Err(shark_error(e)),
+    }
+}
+
+#[endpoint {
+    method = GET,
+    path = "/shark/devices/{dsn}/status",
+}]
+async fn get_device_status(
+    rctx: RequestContext<AppCtx>,
+    path_params: Path<ActionPathParam>,
+) -> Result<HttpResponseOk<SharkDeviceStatus>, HttpError> {
+    let app = rctx.context();
+    let req = &rctx.request;
+    let _ = app.require_auth(&req)?;
+    let (shark, dsn) = resolve_dsn(&app.shark, &path_params.into_inner().dsn).await?;
+
+    match shark.get_device_status(&dsn).await {
+        Ok(status) => Ok(HttpResponseOk(status)),
+        Err(e) => Err(shark_error(e)),
+    }
+}
+
+#[endpoint {
+    method = GET,
+    path = "/shark/devices/{dsn}/maintenance",
+}]
+async fn get_maintenance(
+    rctx: RequestContext<AppCtx>,
+    path_params: Path<ActionPathParam>,
+) -> Result<HttpResponseOk<SharkMaintenance>, HttpError> {
+    let app = rctx.context();
+    let req = &rctx.request;
+    let _ = app.require_auth(&req)?;
+    let (shark, dsn) = resolve_dsn(&app.shark, &path_params.into_inner().dsn).await?;
+
+    match shark.get_maintenance(&dsn).await {
+        Ok(maintenance) => Ok(HttpResponseOk(maintenance)),
+        Err(e) => Err(shark_error(e)),
+    }
+}
+
+#[endpoint {
+    method = GET,
+    path = "/shark/devices/{dsn}/properties/{name}/history",
+}]
+async fn get_property_history(
+    rctx: RequestContext<AppCtx>,
+    path_params: Path<PropertyPathParam>,
+    query: Query<HistoryQueryArgs>,
+) -> Result<HttpResponseOk<Vec<Datapoint<serde_json::Value>>>, HttpError> {
+    let app = rctx.context();
+    let req = &rctx.request;
+    let _ = app.require_auth(&req)?;
+    let path = path_params.into_inner();
+    let query = query.into_inner();
+    let (shark, dsn) = resolve_dsn(&app.shark, &path.dsn).await?;
 
     match shark
-        .set_device_operating_mode(&dsn, shark::OperatingMode::Start)
+        .get_property_history(&dsn, &path.name, query.since, query.limit)
         .await
     {
+        Ok(history) => Ok(HttpResponseOk(history)),
+        Err(e) => Err(shark_error(e)),
+    }
+}
+
+/// Set the device's operating mode. Pause and resume check the current state first so a docked
+/// robot isn't started by a resume.
+async fn set_mode(
+    rctx: &RequestContext<AppCtx>,
+    dsn: &str,
+    mode: ModeAction,
+) -> Result<HttpResponseAccepted<()>, HttpError> {
+    let app = rctx.context();
+    let req = &rctx.request;
+    let _ = app.require_auth(&req)?;
+    let (shark, dsn) = resolve_dsn(&app.shark, dsn).await?;
+    let dsn = dsn.as_str();
+
+    let res = match mode {
+        ModeAction::Start => {
+            shark
+                .set_device_operating_mode(dsn, OperatingMode::Start)
+                .await
+        }
+        ModeAction::Stop => {
+            shark
+                .set_device_operating_mode(dsn, OperatingMode::Stop)
+                .await
+        }
+        ModeAction::Return => {
+            shark
+                .set_device_operating_mode(dsn, OperatingMode::Return)
+                .await
+        }
+        ModeAction::Pause => shark.pause(dsn).await,
+        ModeAction::Resume => shark.resume(dsn).await,
+    };
+
+    match res {
         Ok(_) => Ok(HttpResponseAccepted(())),
-        Err(e) => Err(HttpError::for_internal_error(e.to_string())),
+        Err(e) => Err(shark_error(e)),
     }
 }
 
+#[endpoint {
+    method = PUT,
+    path = "/shark/devices/{dsn}/mode",
+}]
+async fn mode(
+    rctx: RequestContext<AppCtx>,
+    path_params: Path<ActionPathParam>,
+    body_param: TypedBody<ModeArgs>,
+) -> Result<HttpResponseAccepted<()>, HttpError> {
+    let dsn = path_params.into_inner().dsn;
+    set_mode(&rctx, &dsn, body_param.into_inner().mode).await
+}
+
+/// Alias for `PUT /shark/devices/{dsn}/mode` with `{"mode": "start"}`
+#[endpoint {
+    method = PUT,
+    path = "/shark/devices/{dsn}/start",
+}]
+async fn start(
+    rctx: RequestContext<AppCtx>,
+    path_params: Path<ActionPathParam>,
+) -> Result<HttpResponseAccepted<()>, HttpError> {
+    let dsn = path_params.into_inner().dsn;
+    set_mode(&rctx, &dsn, ModeAction::Start).await
+}
+
+/// Alias for `PUT /shark/devices/{dsn}/mode` with `{"mode": "stop"}`
 #[endpoint {
     method = PUT,
     path = "/shark/devices/{dsn}/stop",
@@ -62,42 +291,89 @@ async fn start(
 async fn stop(
     rctx: RequestContext<AppCtx>,
     path_params: Path<ActionPathParam>,
+) -> Result<HttpResponseAccepted<()>, HttpError> {
+    let dsn = path_params.into_inner().dsn;
+    set_mode(&rctx, &dsn, ModeAction::Stop).await
+}
+
+/// Alias for `PUT /shark/devices/{dsn}/mode` with `{"mode": "return"}`
+#[endpoint {
+    method = PUT,
+    path = "/shark/devices/{dsn}/return",
+}]
+async fn r#return(
+    rctx: RequestContext<AppCtx>,
+    path_params: Path<ActionPathParam>,
+) -> Result<HttpResponseAccepted<()>, HttpError> {
+    let dsn = path_params.into_inner().dsn;
+    set_mode(&rctx, &dsn, ModeAction::Return).await
+}
+
+#[endpoint {
+    method = PUT,
+    path = "/shark/devices/{dsn}/locate",
+}]
+async fn locate(
+    rctx: RequestContext<AppCtx>,
+    path_params: Path<ActionPathParam>,
 ) -> Result<HttpResponseAccepted<()>, HttpError> {
     let app = rctx.context();
     let req = &rctx.request;
     let _ = app.require_auth(&req)?;
-    let shark = app.shark.read().await;
-    let dsn = path_params.into_inner().dsn;
+    let (shark, dsn) = resolve_dsn(&app.shark, &path_params.into_inner().dsn).await?;
 
-    match shark
-        .set_device_operating_mode(&dsn, shark::OperatingMode::Stop)
-        .await
-    {
+    match shark.locate(&dsn).await {
         Ok(_) => Ok(HttpResponseAccepted(())),
-        Err(e) => Err(HttpError::for_internal_error(e.to_string())),
+        Err(e) => Err(shark_error(e)),
     }
 }
 
 #[endpoint {
     method = PUT,
-    path = "/shark/devices/{dsn}/return",
+    path = "/shark/devices/{dsn}/power",
 }]
-async fn r#return(
+async fn power(
     rctx: RequestContext<AppCtx>,
     path_params: Path<ActionPathParam>,
+    body_param: TypedBody<PowerArgs>,
 ) -> Result<HttpResponseAccepted<()>, HttpError> {
     let app = rctx.context();
     let req = &rctx.request;
     let _ = app.require_auth(&req)?;
-    let shark = app.shark.read().await;
-    let dsn = path_params.into_inner().dsn;
+    let (shark, dsn) = resolve_dsn(&app.shark, &path_params.into_inner().dsn).await?;
+    let body = body_param.into_inner();
 
-    match shark
-        .set_device_operating_mode(&dsn, shark::OperatingMode::Return)
-        .await
-    {
+    match shark.set_power_mode(&dsn, body.mode).await {
+        Ok(_) => Ok(HttpResponseAccepted(())),
+        Err(e) => Err(shark_error(e)),
+    }
+}
+
+#[endpoint {
+    method = POST,
+    path = "/shark/devices/{dsn}/clean",
+}]
+async fn clean(
+    rctx: RequestContext<AppCtx>,
+    path_params: Path<ActionPathParam>,
+    body_param: TypedBody<CleanArgs>,
+) -> Result<HttpResponseAccepted<()>, HttpError> {
+    let app = rctx.context();
+    let req = &rctx.request;
+    let _ = app.require_auth(&req)?;
+    let (shark, dsn) = resolve_dsn(&app.shark, &path_params.into_inner().dsn).await?;
+    let body = body_param.into_inner();
+
+    if body.rooms.is_empty() {
+        return Err(HttpError::for_bad_request(
+            None,
+            "no rooms provided".to_string(),
+        ));
+    }
+
+    match shark.clean_rooms(&dsn, &body.rooms).await {
         Ok(_) => Ok(HttpResponseAccepted(())),
-        Err(e) => Err(HttpError::for_internal_error(e.to_string())),
+        Err(e) => Err(shark_error(e)),
     }
 }
 
@@ -116,8 +392,20 @@ async fn r#return(
 pub fn mount(api: &mut ApiDescription<AppCtx>) {
     api.register(get_devices)
         .expect("failed to register get_devices");
+    api.register(get_device)
+        .expect("failed to register get_device");
+    api.register(get_device_status)
+        .expect("failed to register get_device_status");
+    api.register(get_maintenance)
+        .expect("failed to register get_maintenance");
+    api.register(get_property_history)
+        .expect("failed to register get_property_history");
+    api.register(mode).expect("failed to register mode");
     api.register(start).expect("failed to register start");
     api.register(stop).expect("failed to register stop");
     api.register(r#return).expect("failed to register return");
+    api.register(locate).expect("failed to register locate");
+    api.register(power).expect("failed to register power");
+    api.register(clean).expect("failed to register clean");
     // api.register(example).expect("failed to register return");
 }
@@ -0,0 +1,84 @@
+use crate::shark_accounts::SharkAccount;
+use crate::AppCtx;
+use futures::stream::{self, StreamExt};
+use shark::{PropertyChange, SharkEvent};
+use slog::Logger;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::time::{self, Instant};
+
+/// How often an account's devices are listed again to pick up devices added since
+const RELIST_INTERVAL: Duration = Duration::from_secs(600);
+/// First delay before listing devices again after a failure, doubled for each failure in a row
+const RELIST_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Poll every device of a shark account for state changes and publish them to `events`.
+pub async fn watch_devices(
+    account: Arc<SharkAccount>,
+    events: broadcast::Sender<SharkEvent>,
+    log: Logger,
+) {
+    let interval = account.watch_interval;
+    let mut watched = HashSet::new();
+    let mut changes = stream::SelectAll::new();
+    let mut backoff = RELIST_BACKOFF;
+    let relist = time::sleep(Duration::ZERO);
+    tokio::pin!(relist);
+
+    loop {
+        tokio::select! {
+            _ = &mut relist => {
+                let delay = match account.client.get_devices().await {
+                    Ok(devices) => {
+                        backoff = RELIST_BACKOFF;
+                        for device in devices {
+                            if watched.insert(device.dsn.clone()) {
+                                info!(&log, "watching shark device {}", device.dsn);
+                                changes.push(Box::pin(account.client.watch(device.dsn, interval)));
+                            }
+                        }
+                        RELIST_INTERVAL
+                    }
+                    Err(e) => {
+                        error!(
+                            &log,
+                            "failed to list shark devices, retrying in {:?}: {}", backoff, e
+                        );
+                        let delay = backoff;
+                        backoff = (backoff * 2).min(RELIST_INTERVAL);
+                        delay
+                    }
+                };
+                relist.as_mut().reset(Instant::now() + delay);
+            }
+            Some(event) = changes.next(), if !changes.is_empty() => match event {
+                // An error only means nobody is subscribed right now
+                Ok(event) => {
+                    let _ = events.send(event);
+                }
+                Err(e) => warn!(&log, "failed to poll shark device: {}", e),
+            },
+        }
+    }
+}
+
+/// Log every shark state change.
+pub async fn log_events(app: AppCtx, log: Logger) {
+    let mut events = app.shark_events.subscribe();
+    loop {
+        match events.recv().await {
+            // Properties without a typed field in `SharkDeviceProperties`. The shark crate doesn't
+            // know what they mean, so they are only logged when debugging
+            Ok(event) if matches!(event.change, PropertyChange::Other { .. }) => {
+                debug!(&log, "shark event: {:?}", event)
+            }
+            Ok(event) => info!(&log, "shark event: {:?}", event),
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                warn!(&log, "missed {} shark events", n)
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
@@ -1,20 +1,80 @@
+use crate::error::ApiError;
 use crate::models::AylaLoginResponse;
+use crate::retry::{self, RateLimiter, RetryPolicy};
+use crate::token_store::{SharkTokens, TokenStore};
 use crate::{error, Region};
 use reqwest::{header, Client, Method, Request, Response, StatusCode, Url};
 use serde::Serialize;
 use serde_json::json;
+use std::time::{Duration, SystemTime};
+use tokio::sync::RwLock;
 
 type Result<T> = std::result::Result<T, error::AylaError>;
 
+/// Refresh the access token when it is this close to expiring
+const REFRESH_MARGIN: Duration = Duration::from_secs(10 * 60);
+
+/// Everything needed to construct an `AylaClient`
+pub(crate) struct AylaConfig {
+    pub region: Region,
+    pub user_url: Url,
+    pub device_url: Url,
+    pub email: String,
+    pub password: String,
+    pub token_store: Option<Box<dyn TokenStore>>,
+    pub timeout: Duration,
+    pub retry_policy: RetryPolicy,
+    pub min_request_interval: Duration,
+}
+
 pub(crate) struct AylaClient {
     client: Client,
     region: Region,
+    user_url: Url,
+    device_url: Url,
+    retry_policy: RetryPolicy,
+    limiter: RateLimiter,
     email: String,
     password: String,
+    auth: RwLock<AuthState>,
+    store: Option<Box<dyn TokenStore>>,
+}
+
+#[derive(Default)]
+struct AuthState {
     access_token: Option<String>,
     refresh_token: Option<String>,
-    auth_expiration: Option<String>,
-    is_authenticated: bool,
+    auth_expiration: Option<SystemTime>,
+}
+
+impl AuthState {
+    fn update(&mut self, alr: AylaLoginResponse) {
+        self.access_token = Some(alr.access_token);
+        self.refresh_token = Some(alr.refresh_token);
+        self.auth_expiration = Some(SystemTime::now() + Duration::from_secs(alr.expires_in));
+    }
+
+    fn restore(&mut self, tokens: SharkTokens) {
+        self.access_token = Some(tokens.access_token);
+        self.refresh_token = Some(tokens.refresh_token);
+        self.auth_expiration = Some(tokens.expires_at);
+    }
+
+    fn tokens(&self, email: &str) -> Option<SharkTokens> {
+        Some(SharkTokens {
+            email: email.to_string(),
+            access_token: self.access_token.clone()?,
+            refresh_token: self.refresh_token.clone()?,
+            expires_at: self.auth_expiration?,
+        })
+    }
+
+    fn needs_refresh(&self) -> bool {
+        match self.auth_expiration {
+            Some(expiration) => SystemTime::now() + REFRESH_MARGIN >= expiration,
+            None => false,
+        }
+    }
 }
 
 pub(crate) enum RequestType {
@@ -23,7 +83,7 @@ pub(crate) enum RequestType {
 }
 
 impl AylaClient {
-    pub(crate) fn new(region: Region, email: String, password: String) -> Self {
+    pub(crate) fn new(config: AylaConfig) -> Self {
         let mut headers = header::HeaderMap::new();
         headers.append(
             header::CONTENT_TYPE,
@@ -33,16 +93,20 @@ impl AylaClient {
             header::ACCEPT,
             header::HeaderValue::from_static("application/json"),
         );
-        let client = Client::builder().default_headers(headers).build().unwrap();
+        let client = Client::builder()
+            .default_headers(headers)
+            .timeout(config.timeout);
         Self {
-            client,
-            region,
-            email,
-            password,
-            access_token: None,
-            refresh_token: None,
-            auth_expiration: None,
-            is_authenticated: false,
+            client: client.build().unwrap(),
+            region: config.region,
+            user_url: config.user_url,
+            device_url: config.device_url,
+            retry_policy: config.retry_policy,
+            limiter: RateLimiter::new(config.min_request_interval),
+            email: config.email,
+            password: config.password,
+            auth: RwLock::new(AuthState::default()),
+            store: config.token_store,
         }
     }
 
@@ -58,24 +122,13 @@ impl AylaClient {
         B: Serialize,
     {
         let base = match req_type {
-            RequestType::Device => Url::parse(self.region.device_url()).unwrap(),
-            RequestType::User => Url::parse(self.region.user_url()).unwrap(),
+            RequestType::Device => &self.device_url,
+            RequestType::User => &self.user_url,
         };
 
         let url = base.join(path.as_ref()).unwrap();
         let mut rb = self.client.request(method, url);
 
-        if self.is_authenticated {
-            if let Some(token) = &self.access_token {
-                let mut headers = header::HeaderMap::new();
-                headers.append(
-                    header::AUTHORIZATION,
-                    header::HeaderValue::from_str(token).unwrap(),
-                );
-                rb = rb.headers(headers);
-            }
-        }
-
         if let Some(b) = body {
             rb = rb.json(&b);
         }
@@ -83,51 +136,159 @@ impl AylaClient {
         Ok(rb.build()?)
     }
 
+    /// Execute an authenticated request. The access token is refreshed before it expires, and a
+    /// request rejected with a 401 is retried once after refreshing the token or signing in
+    /// again.
     pub(crate) async fn execute(&self, request: Request) -> Result<Response> {
-        let res = self.client.execute(request).await?;
-        Ok(res)
-    }
-
-    pub(crate) async fn sign_in(&mut self) -> Result<()> {
-        if !self.is_authenticated {
-            let body = json!({
-                "user": {
-                    "email": self.email,
-                    "password": self.password,
-                    "application": {
-                        "app_id": self.region.app_id(),
-                        "app_secret": self.region.app_secret(),
+        if self.auth.read().await.needs_refresh() {
+            let mut auth = self.auth.write().await;
+            // Another request may have refreshed the token while we waited for the lock
+            if auth.needs_refresh() {
+                self.reauthenticate(&mut auth).await?;
+            }
+        }
+
+        let retry = request.try_clone();
+        let token = self.auth.read().await.access_token.clone();
+        let res = self.send(request, token.as_deref()).await?;
+
+        match (res.status(), retry) {
+            (StatusCode::UNAUTHORIZED, Some(retry)) => {
+                let token = {
+                    let mut auth = self.auth.write().await;
+                    if auth.access_token == token {
+                        self.reauthenticate(&mut auth).await?;
                     }
-                }
-            });
+                    auth.access_token.clone()
+                };
+                self.send(retry, token.as_deref()).await
+            }
+            _ => Ok(res),
+        }
+    }
 
-            let req = self.request(
-                RequestType::User,
-                Method::POST,
-                "/users/sign_in",
-                Some(body),
-            )?;
+    /// Send a request, retrying it with backoff according to the retry policy
+    async fn send(&self, mut request: Request, token: Option<&str>) -> Result<Response> {
+        if let Some(token) = token {
+            request.headers_mut().insert(
+                header::AUTHORIZATION,
+                header::HeaderValue::from_str(token).unwrap(),
+            );
+        }
 
-            let res = self.client.execute(req).await?;
-            match res.status() {
-                StatusCode::OK => {
-                    let alr: AylaLoginResponse = res.json().await?;
-                    self.access_token = Some(alr.access_token);
-                    self.refresh_token = Some(alr.refresh_token);
-                    self.auth_expiration = None;
-                    self.is_authenticated = true;
+        let idempotent = retry::is_idempotent(request.method());
+        let mut attempt = 0;
+        loop {
+            let next = if attempt < self.retry_policy.max_retries {
+                request.try_clone()
+            } else {
+                None
+            };
+
+            if let Some(remaining) = self.limiter.blocked_for() {
+                return Err(error::AylaError::RateLimited(ApiError {
+                    status: StatusCode::TOO_MANY_REQUESTS,
+                    message: format!("retry in {}s", remaining.as_secs().max(1)),
+                }));
+            }
+
+            self.limiter.acquire().await;
+            let res = self.client.execute(request).await;
+            let next = match (next, &res) {
+                (next, Ok(r)) if r.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    let delay =
+                        retry::retry_after(r).unwrap_or_else(|| self.retry_policy.backoff(attempt));
+                    if delay > self.retry_policy.max_backoff {
+                        // Deferring would hold back every request of this client for that long,
+                        // so they fail until then instead
+                        self.limiter.block(delay);
+                        return Ok(res?);
+                    }
+                    self.limiter.defer(delay).await;
+                    match next {
+                        Some(next) => next,
+                        None => return Ok(res?),
+                    }
+                }
+                (Some(next), Ok(r)) if idempotent && r.status().is_server_error() => {
+                    tokio::time::sleep(self.retry_policy.backoff(attempt)).await;
+                    next
                 }
-                sc => {
-                    return Err(error::AylaError::LoginError(sc, res.text().await.unwrap()));
+                // A connection error means the request never reached Ayla
+                (Some(next), Err(e)) if e.is_connect() || (idempotent && e.is_timeout()) => {
+                    tokio::time::sleep(self.retry_policy.backoff(attempt)).await;
+                    next
                 }
+                _ => return Ok(res?),
             };
+
+            request = next;
+            attempt += 1;
+        }
+    }
+
+    /// Persist the current tokens if a token store is configured
+    fn save_tokens(&self, auth: &AuthState) {
+        if let (Some(store), Some(tokens)) = (&self.store, auth.tokens(&self.email)) {
+            // Failing to persist the tokens only costs us a full sign in on the next start, so
+            // don't fail an otherwise successful sign in or refresh over it.
+            let _ = store.save(&tokens);
+        }
+    }
+
+    /// Load previously stored tokens belonging to this account
+    fn stored_tokens(&self) -> Option<SharkTokens> {
+        let tokens = self.store.as_ref()?.load().ok()??;
+        (tokens.email == self.email).then_some(tokens)
+    }
+
+    /// Try to refresh the access token, falling back to signing in again
+    async fn reauthenticate(&self, auth: &mut AuthState) -> Result<()> {
+        if auth.refresh_token.is_some() && self.refresh(auth).await.is_ok() {
+            return Ok(());
         }
 
+        self.login(auth).await
+    }
+
+    async fn login(&self, auth: &mut AuthState) -> Result<()> {
+        let body = json!({
+            "user": {
+                "email": self.email,
+                "password": self.password,
+                "application": {
+                    "app_id": self.region.app_id(),
+                    "app_secret": self.region.app_secret(),
+                }
+            }
+        });
+
+        let req = self.request(
+            RequestType::User,
+            Method::POST,
+            "/users/sign_in",
+            Some(body),
+        )?;
+
+        let res = self.send(req, None).await?;
+        match res.status() {
+            StatusCode::OK => {
+                let alr: AylaLoginResponse = res.json().await?;
+                auth.update(alr);
+                self.save_tokens(auth);
+            }
+            _ => {
+                return Err(error::AylaError::LoginError(
+                    ApiError::from_response(res).await,
+                ));
+            }
+        };
+
         Ok(())
     }
 
-    pub(crate) async fn refresh_token(&mut self) -> Result<()> {
-        let body = json!({"user": {"refresh_token": self.refresh_token }});
+    async fn refresh(&self, auth: &mut AuthState) -> Result<()> {
+        let body = json!({"user": {"refresh_token": auth.refresh_token }});
         let req = self.request(
             RequestType::User,
             Method::POST,
@@ -135,19 +296,16 @@ impl AylaClient {
             Some(body),
         )?;
 
-        let res = self.client.execute(req).await?;
+        let res = self.send(req, None).await?;
         match res.status() {
             StatusCode::OK => {
                 let alr: AylaLoginResponse = res.json().await?;
-                self.access_token = Some(alr.access_token);
-                self.refresh_token = Some(alr.refresh_token);
-                self.auth_expiration = None;
-                self.is_authenticated = true;
+                auth.update(alr);
+                self.save_tokens(auth);
             }
-            sc => {
+            _ => {
                 return Err(error::AylaError::RefreshTokenError(
-                    sc,
-                    res.text().await.unwrap(),
+                    ApiError::from_response(res).await,
                 ));
             }
         };
@@ -155,20 +313,51 @@ impl AylaClient {
         Ok(())
     }
 
-    pub(crate) async fn sign_out(self) -> Result<()> {
-        if self.is_authenticated {
-            let body = json!({"user": {"access_token": self.access_token }});
+    /// Sign in reusing stored tokens when possible, refreshing them if they are about to
+    /// expire, before falling back to a full login.
+    pub(crate) async fn sign_in(&self) -> Result<()> {
+        let mut auth = self.auth.write().await;
+        if auth.access_token.is_some() {
+            return Ok(());
+        }
+
+        if let Some(tokens) = self.stored_tokens() {
+            auth.restore(tokens);
+            if !auth.needs_refresh() || self.refresh(&mut auth).await.is_ok() {
+                return Ok(());
+            }
+        }
+
+        self.login(&mut auth).await
+    }
+
+    pub(crate) async fn refresh_token(&self) -> Result<()> {
+        let mut auth = self.auth.write().await;
+        self.refresh(&mut auth).await
+    }
+
+    pub(crate) async fn sign_out(&self) -> Result<()> {
+        let mut auth = self.auth.write().await;
+        if let Some(token) = auth.access_token.clone() {
+            let body = json!({"user": {"access_token": token }});
             let req = self.request(
                 RequestType::User,
                 Method::POST,
                 "/users/sign_out",
                 Some(body),
             )?;
-            let res = self.client.execute(req).await?;
+            let res = self.send(req, Some(&token)).await?;
             match res.status() {
-                StatusCode::OK => (),
-                sc => {
-                    return Err(error::AylaError::LogoutError(sc, res.text().await.unwrap()));
+                StatusCode::OK => {
+                    *auth = AuthState::default();
+                    if let Some(store) = &self.store {
+                        let _ = store.clear();
+                    }
+                }
+                _ => {
+                    return Err(error::AylaError::LogoutError(
+                        ApiError::from_response(res).await,
+                    ));
                 }
             };
         }
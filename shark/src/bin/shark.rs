@@ -0,0 +1,251 @@
+//! A command line client for Shark robots, mostly useful to debug a robot from a terminal.
+//!
+//! Credentials are read from a TOML config file given with `-c` holding `user`, `password` and
+//! optionally `region` and `token_file`. Each can be overridden by the `SHARK_USER`,
+//! `SHARK_PASSWORD`, `SHARK_REGION` and `SHARK_TOKEN_FILE` environment variables.
+
+use serde::{Deserialize, Serialize};
+use shark::{
+    FileTokenStore, OperatingMode, PowerMode, SharkClient, SharkDevice, SharkDeviceProperties,
+    TokenStore,
+};
+use std::fs;
+use std::path::PathBuf;
+
+const COMMANDS: &str = "
+Commands:
+    login                   sign in and save the session to the token file
+    devices                 list the account's devices
+    properties DEVICE       show the device's properties
+    start DEVICE            start cleaning
+    stop DEVICE             stop cleaning
+    pause DEVICE            pause the current clean
+    resume DEVICE           resume a paused clean
+    return DEVICE           return to the dock
+    power DEVICE MODE       set the suction power to normal, eco or max
+    refresh                 refresh the session token
+
+DEVICE is either a device's DSN or its name.";
+
+#[derive(Deserialize, Default)]
+struct Config {
+    user: Option<String>,
+    password: Option<String>,
+    /// "us" or "eu", defaults to "us"
+    region: Option<String>,
+    /// Where the session is kept between runs, defaults to ~/.shark-tokens.json
+    token_file: Option<PathBuf>,
+}
+
+impl Config {
+    fn load(path: Option<String>) -> Result<Self, String> {
+        let mut config: Self = match path {
+            Some(path) => {
+                let buf = fs::read(&path).map_err(|e| format!("{}: {}", path, e))?;
+                toml::from_slice(&buf).map_err(|e| format!("{}: {}", path, e))?
+            }
+            None => Self::default(),
+        };
+
+        if let Ok(user) = std::env::var("SHARK_USER") {
+            config.user = Some(user);
+        }
+        if let Ok(password) = std::env::var("SHARK_PASSWORD") {
+            config.password = Some(password);
+        }
+        if let Ok(region) = std::env::var("SHARK_REGION") {
+            config.region = Some(region);
+        }
+        if let Some(token_file) = std::env::var_os("SHARK_TOKEN_FILE") {
+            config.token_file = Some(token_file.into());
+        }
+        if config.token_file.is_none() {
+            config.token_file =
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".shark-tokens.json"));
+        }
+
+        Ok(config)
+    }
+
+    fn token_store(&self) -> Option<FileTokenStore> {
+        self.token_file.as_ref().map(FileTokenStore::new)
+    }
+
+    async fn client(&self) -> Result<SharkClient, String> {
+        let user = self.user.as_ref().ok_or("no user configured")?;
+        let password = self.password.as_ref().ok_or("no password configured")?;
+
+        let mut builder = SharkClient::builder(user, password);
+        if let Some(region) = &self.region {
+            builder = builder.region(region.parse()?);
+        }
+        if let Some(store) = self.token_store() {
+            builder = builder.token_store(store);
+        }
+        builder.build().await.map_err(|e| e.to_string())
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    if let Err(e) = run().await {
+        eprintln!("shark: {}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run() -> Result<(), String> {
+    let args: Vec<String> = std::env::args().collect();
+    let brief = format!("Usage: {} [options] COMMAND [ARGS]", args[0]);
+
+    let mut opts = getopts::Options::new();
+    opts.optopt("c", "config", "config file", "CONFIG");
+    opts.optflag("j", "json", "print JSON instead of text");
+    opts.optflag("h", "help", "print this help");
+    let usage = format!("{}{}", opts.usage(&brief), COMMANDS);
+    let matches = opts
+        .parse(&args[1..])
+        .map_err(|e| format!("{}\n{}", e, usage))?;
+
+    if matches.opt_present("h") {
+        println!("{}", usage);
+        return Ok(());
+    }
+    let json = matches.opt_present("j");
+    let config = Config::load(matches.opt_str("c"))?;
+
+    let (command, args) = match matches.free.split_first() {
+        Some((command, args)) => (command.as_str(), args),
+        None => return Err(format!("no command given\n{}", usage)),
+    };
+
+    if command == "login" {
+        // Sign in again even if a session was saved by an earlier login
+        if let Some(store) = config.token_store() {
+            store.clear().map_err(|e| e.to_string())?;
+        }
+    }
+    let client = config.client().await?;
+
+    match (command, args) {
+        ("login", []) => match &config.token_file {
+            Some(path) => eprintln!("signed in, session saved to {}", path.display()),
+            None => eprintln!("signed in"),
+        },
+        ("devices", []) => {
+            let devices = client.get_devices().await.map_err(|e| e.to_string())?;
+            print(json, &devices, |d| print_devices(d))?;
+        }
+        ("properties", [device]) => {
+            let dsn = resolve(&client, device).await?;
+            let properties = client
+                .get_device_properties(&dsn)
+                .await
+                .map_err(|e| e.to_string())?;
+            print(json, &properties, print_properties)?;
+        }
+        ("start", [device]) => set_mode(&client, device, OperatingMode::Start).await?,
+        ("stop", [device]) => set_mode(&client, device, OperatingMode::Stop).await?,
+        ("return", [device]) => set_mode(&client, device, OperatingMode::Return).await?,
+        ("pause", [device]) => {
+            let dsn = resolve(&client, device).await?;
+            client.pause(&dsn).await.map_err(|e| e.to_string())?;
+        }
+        ("resume", [device]) => {
+            let dsn = resolve(&client, device).await?;
+            client.resume(&dsn).await.map_err(|e| e.to_string())?;
+        }
+        ("power", [device, mode]) => {
+            let mode: PowerMode = serde_json::from_value(serde_json::json!(mode))
+                .map_err(|_| format!("unknown power mode: {}", mode))?;
+            let dsn = resolve(&client, device).await?;
+            client
+                .set_power_mode(&dsn, mode)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+        ("refresh", []) => {
+            client.refresh_token().await.map_err(|e| e.to_string())?;
+            eprintln!("session refreshed");
+        }
+        _ => return Err(format!("invalid command: {}\n{}", command, usage)),
+    }
+
+    Ok(())
+}
+
+async fn resolve(client: &SharkClient, device: &str) -> Result<String, String> {
+    client
+        .resolve_device(device)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+async fn set_mode(client: &SharkClient, device: &str, mode: OperatingMode) -> Result<(), String> {
+    let dsn = resolve(client, device).await?;
+    client
+        .set_device_operating_mode(&dsn, mode)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Print `value` as JSON or as text using `text`
+fn print<T: Serialize>(json: bool, value: &T, text: impl FnOnce(&T)) -> Result<(), String> {
+    if json {
+        let out = serde_json::to_string_pretty(value).map_err(|e| e.to_string())?;
+        println!("{}", out);
+    } else {
+        text(value);
+    }
+    Ok(())
+}
+
+fn print_devices(devices: &[SharkDevice]) {
+    for d in devices {
+        let status = match &d.connection_status {
+            Some(status) => format!("{:?}", status).to_lowercase(),
+            None => "unknown".to_string(),
+        };
+        println!(
+            "{:<20} {:<12} {:<10} {}",
+            d.dsn, status, d.model, d.product_name
+        );
+    }
+}
+
+fn print_properties(p: &SharkDeviceProperties) {
+    fn percent(v: Option<u8>) -> Option<String> {
+        v.map(|v| format!("{}%", v))
+    }
+    fn debug<T: std::fmt::Debug>(v: Option<T>) -> Option<String> {
+        v.map(|v| format!("{:?}", v).to_lowercase())
+    }
+
+    let mut lines = vec![
+        ("battery", percent(p.battery_capacity)),
+        ("operating mode", debug(p.operating_mode)),
+        ("power mode", debug(p.power_mode)),
+        ("charging", p.charging_status.map(|v| v.to_string())),
+        ("error code", p.error_code.map(|v| v.to_string())),
+        ("rssi", p.rssi.map(|v| v.to_string())),
+        ("firmware", p.firmware_version.clone()),
+        ("nav firmware", p.nav_firmware_version.clone()),
+        ("rooms", p.rooms.as_ref().map(|r| r.join(", "))),
+        ("main brush life", percent(p.main_brush_life)),
+        ("side brush life", percent(p.side_brush_life)),
+        ("filter life", percent(p.filter_life)),
+    ];
+    let mut extra: Vec<_> = p.extra.iter().collect();
+    extra.sort_by(|a, b| a.0.cmp(b.0));
+    lines.extend(
+        extra
+            .into_iter()
+            .map(|(name, value)| (name.as_str(), Some(value.to_string()))),
+    );
+
+    for (name, value) in lines {
+        if let Some(value) = value {
+            println!("{:<24} {}", name, value);
+        }
+    }
+}
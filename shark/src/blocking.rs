@@ -0,0 +1,276 @@
+//! A blocking client for the Shark API.
+//!
+//! `SharkClient` mirrors the async `crate::SharkClient` for scripts and tools that don't want to
+//! run a tokio runtime of their own. Each client drives the async client on a private single
+//! threaded runtime, so it must not be used from within an async context.
+
+use crate::error::SharkError;
+use crate::region::Region;
+use crate::{
+    Datapoint, LanConfig, LanStats, OperatingMode, PowerMode, Result, RetryPolicy, SharkDevice,
+    SharkDeviceProperties, SharkDeviceStatus, SharkEvent, SharkMaintenance, TokenStore, Transport,
+    Url,
+};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use serde::{de::DeserializeOwned, Serialize};
+use std::time::Duration;
+use tokio::runtime::{self, Runtime};
+
+pub struct SharkClientBuilder {
+    inner: crate::SharkClientBuilder,
+}
+
+impl SharkClientBuilder {
+    pub fn region(self, region: Region) -> Self {
+        Self {
+            inner: self.inner.region(region),
+        }
+    }
+
+    /// Override the Ayla user service URL of the selected region
+    pub fn user_url(self, url: Url) -> Self {
+        Self {
+            inner: self.inner.user_url(url),
+        }
+    }
+
+    /// Override the Ayla device service URL of the selected region
+    pub fn device_url(self, url: Url) -> Self {
+        Self {
+            inner: self.inner.device_url(url),
+        }
+    }
+
+    /// Persist session tokens in `store` so they can be reused instead of signing in again
+    pub fn token_store<S: TokenStore + 'static>(self, store: S) -> Self {
+        Self {
+            inner: self.inner.token_store(store),
+        }
+    }
+
+    /// Fail requests to the Ayla cloud that take longer than `timeout`, defaults to 30 seconds
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self {
+            inner: self.inner.timeout(timeout),
+        }
+    }
+
+    /// Set how failed requests are retried, defaults to `RetryPolicy::default()`
+    pub fn retry_policy(self, policy: RetryPolicy) -> Self {
+        Self {
+            inner: self.inner.retry_policy(policy),
+        }
+    }
+
+    /// Wait at least `interval` between requests to the Ayla cloud
+    pub fn rate_limit(self, interval: Duration) -> Self {
+        Self {
+            inner: self.inner.rate_limit(interval),
+        }
+    }
+
+    /// Reuse device lists and properties fetched from the Ayla cloud for up to `ttl`
+    pub fn cache_ttl(self, ttl: Duration) -> Self {
+        Self {
+            inner: self.inner.cache_ttl(ttl),
+        }
+    }
+
+    /// Enable Ayla's LAN mode so robots switched to `Transport::Lan` are controlled over the
+    /// local network
+    pub fn lan(self, config: LanConfig) -> Self {
+        Self {
+            inner: self.inner.lan(config),
+        }
+    }
+
+    /// Given the provided credentials attempt to login to the Shark API service, reusing any
+    /// tokens found in the token store
+    pub fn build(self) -> Result<SharkClient> {
+        let rt = runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(SharkError::RuntimeError)?;
+        let inner = rt.block_on(self.inner.build())?;
+
+        Ok(SharkClient { inner, rt })
+    }
+}
+
+pub struct SharkClient {
+    inner: crate::SharkClient,
+    rt: Runtime,
+}
+
+impl SharkClient {
+    pub fn builder<E, P>(email: E, password: P) -> SharkClientBuilder
+    where
+        E: Into<String>,
+        P: Into<String>,
+    {
+        SharkClientBuilder {
+            inner: crate::SharkClient::builder(email, password),
+        }
+    }
+
+    /// Get all devices on the account, reusing a recently fetched list
+    pub fn get_devices(&self) -> Result<Vec<SharkDevice>> {
+        self.rt.block_on(self.inner.get_devices())
+    }
+
+    /// Fetch all devices on the account from the Ayla cloud, bypassing the cache
+    pub fn refresh_devices(&self) -> Result<Vec<SharkDevice>> {
+        self.rt.block_on(self.inner.refresh_devices())
+    }
+
+    /// Resolve the DSN of a device given either its DSN or its user-assigned name, ignoring case
+    pub fn resolve_device(&self, name: &str) -> Result<String> {
+        self.rt.block_on(self.inner.resolve_device(name))
+    }
+
+    pub fn get_device(&self, dsn: &str) -> Result<SharkDevice> {
+        self.rt.block_on(self.inner.get_device(dsn))
+    }
+
+    /// Fetch the device from the Ayla cloud, bypassing the cache
+    pub fn refresh_device(&self, dsn: &str) -> Result<SharkDevice> {
+        self.rt.block_on(self.inner.refresh_device(dsn))
+    }
+
+    /// Get all of the device's properties, reusing recently fetched ones
+    pub fn get_device_properties(&self, dsn: &str) -> Result<SharkDeviceProperties> {
+        self.rt.block_on(self.inner.get_device_properties(dsn))
+    }
+
+    /// Fetch all of the device's properties from the Ayla cloud, bypassing the cache
+    pub fn refresh_device_properties(&self, dsn: &str) -> Result<SharkDeviceProperties> {
+        self.rt.block_on(self.inner.refresh_device_properties(dsn))
+    }
+
+    /// Forget all cached devices and properties
+    pub fn invalidate_cache(&self) {
+        self.inner.invalidate_cache()
+    }
+
+    /// Get a summary of the device's battery, operating mode, charging and connection state
+    pub fn get_device_status(&self, dsn: &str) -> Result<SharkDeviceStatus> {
+        self.rt.block_on(self.inner.get_device_status(dsn))
+    }
+
+    /// Read the current value of the Ayla property `name`
+    pub fn get_property<T>(&self, dsn: &str, name: &str) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        self.rt.block_on(self.inner.get_property(dsn, name))
+    }
+
+    /// Get the values reported for the Ayla property `name`, oldest first. Only values reported
+    /// since `since` are returned if given, and at most `limit` of them.
+    pub fn get_property_history<T>(
+        &self,
+        dsn: &str,
+        name: &str,
+        since: Option<DateTime<Utc>>,
+        limit: Option<u32>,
+    ) -> Result<Vec<Datapoint<T>>>
+    where
+        T: DeserializeOwned,
+    {
+        self.rt
+            .block_on(self.inner.get_property_history(dsn, name, since, limit))
+    }
+
+    /// Write a new datapoint to the Ayla property `name`
+    pub fn set_property<T>(&self, dsn: &str, name: &str, value: T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        self.rt.block_on(self.inner.set_property(dsn, name, value))
+    }
+
+    /// Select how commands are sent to the device. Switching to `Transport::Lan` fetches the
+    /// device's address and LAN key from the cloud, so it has to be done while online.
+    pub fn set_transport(&self, dsn: &str, transport: Transport) -> Result<()> {
+        self.rt.block_on(self.inner.set_transport(dsn, transport))
+    }
+
+    /// How commands are currently sent to the device
+    pub fn transport(&self, dsn: &str) -> Transport {
+        self.inner.transport(dsn)
+    }
+
+    /// How often commands to the device were sent through the cloud because it couldn't be
+    /// reached over the local network, `None` unless it uses `Transport::Lan`
+    pub fn lan_stats(&self, dsn: &str) -> Option<LanStats> {
+        self.inner.lan_stats(dsn)
+    }
+
+    /// Get the remaining life of the device's brushes and filter
+    pub fn get_maintenance(&self, dsn: &str) -> Result<SharkMaintenance> {
+        self.rt.block_on(self.inner.get_maintenance(dsn))
+    }
+
+    pub fn set_device_operating_mode(&self, dsn: &str, mode: OperatingMode) -> Result<()> {
+        self.rt
+            .block_on(self.inner.set_device_operating_mode(dsn, mode))
+    }
+
+    /// Pause the current clean, fails if the robot isn't cleaning
+    pub fn pause(&self, dsn: &str) -> Result<()> {
+        self.rt.block_on(self.inner.pause(dsn))
+    }
+
+    /// Resume a paused clean, fails if the robot isn't paused
+    pub fn resume(&self, dsn: &str) -> Result<()> {
+        self.rt.block_on(self.inner.resume(dsn))
+    }
+
+    pub fn get_operating_mode(&self, dsn: &str) -> Result<Option<OperatingMode>> {
+        self.rt.block_on(self.inner.get_operating_mode(dsn))
+    }
+
+    pub fn set_power_mode(&self, dsn: &str, mode: PowerMode) -> Result<()> {
+        self.rt.block_on(self.inner.set_power_mode(dsn, mode))
+    }
+
+    /// Make the robot beep so it can be found
+    pub fn locate(&self, dsn: &str) -> Result<()> {
+        self.rt.block_on(self.inner.locate(dsn))
+    }
+
+    /// Get the names of the rooms on the robot's map
+    pub fn get_rooms(&self, dsn: &str) -> Result<Vec<String>> {
+        self.rt.block_on(self.inner.get_rooms(dsn))
+    }
+
+    /// Start cleaning only the given rooms. Room names are matched case-insensitively against
+    /// the rooms returned by `get_rooms`.
+    pub fn clean_rooms<S: AsRef<str>>(&self, dsn: &str, rooms: &[S]) -> Result<()> {
+        self.rt.block_on(self.inner.clean_rooms(dsn, rooms))
+    }
+
+    /// Poll the device's properties every `interval` and yield an event for each property that
+    /// changed since the previous poll. Polling errors are yielded without ending the iterator.
+    pub fn watch<S: Into<String>>(
+        &self,
+        dsn: S,
+        interval: Duration,
+    ) -> impl Iterator<Item = Result<SharkEvent>> + '_ {
+        let mut events = Box::pin(self.inner.watch(dsn, interval));
+        std::iter::from_fn(move || self.rt.block_on(events.next()))
+    }
+
+    /// Refresh API token. Tokens are refreshed automatically before they expire so this is only
+    /// needed to force a refresh.
+    pub fn refresh_token(&self) -> Result<()> {
+        self.rt.block_on(self.inner.refresh_token())
+    }
+
+    /// Sign out of the Shark API. Signing out again is a no-op, while any other request signs in
+    /// again.
+    pub fn sign_out(&self) -> Result<()> {
+        self.rt.block_on(self.inner.sign_out())
+    }
+}
@@ -0,0 +1,76 @@
+use crate::{SharkDevice, SharkDeviceProperties};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long responses from the Ayla cloud are reused unless configured otherwise
+pub(crate) const DEFAULT_TTL: Duration = Duration::from_secs(30);
+
+/// Values that expire `ttl` after they were stored
+pub(crate) struct TtlCache<K, V> {
+    ttl: Duration,
+    entries: Mutex<HashMap<K, (Instant, V)>>,
+}
+
+impl<K: Eq + Hash, V: Clone> TtlCache<K, V> {
+    pub(crate) fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The value stored for `key` unless it has expired
+    pub(crate) fn get(&self, key: &K) -> Option<V> {
+        let entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((stored, value)) if stored.elapsed() < self.ttl => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn insert(&self, key: K, value: V) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(key, (Instant::now(), value));
+    }
+
+    pub(crate) fn remove(&self, key: &K) {
+        self.entries.lock().unwrap().remove(key);
+    }
+
+    pub(crate) fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+/// Responses from the Ayla cloud that are reused by `SharkClient` until they expire or are
+/// invalidated by a write to the device.
+pub(crate) struct SharkCache {
+    pub(crate) devices: TtlCache<(), Vec<SharkDevice>>,
+    pub(crate) device: TtlCache<String, SharkDevice>,
+    pub(crate) properties: TtlCache<String, SharkDeviceProperties>,
+}
+
+impl SharkCache {
+    pub(crate) fn new(ttl: Duration) -> Self {
+        Self {
+            devices: TtlCache::new(ttl),
+            device: TtlCache::new(ttl),
+            properties: TtlCache::new(ttl),
+        }
+    }
+
+    /// Forget everything cached about `dsn`
+    pub(crate) fn invalidate(&self, dsn: &str) {
+        let dsn = dsn.to_string();
+        self.device.remove(&dsn);
+        self.properties.remove(&dsn);
+    }
+
+    pub(crate) fn clear(&self) {
+        self.devices.clear();
+        self.device.clear();
+        self.properties.clear();
+    }
+}
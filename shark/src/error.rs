@@ -1,24 +1,174 @@
-use reqwest::StatusCode;
+use reqwest::{Response, StatusCode};
+use serde::Deserialize;
+use std::fmt;
 use thiserror::Error;
 
+/// An error response returned by the Ayla cloud.
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    pub status: StatusCode,
+    /// The error message(s) reported by Ayla, or the raw body if it wasn't a JSON error
+    pub message: String,
+}
+
+/// Ayla reports errors as either `{"error": "..."}` or `{"errors": ...}` where the latter is a
+/// string, a list of strings or a map of field names to lists of strings.
+#[derive(Deserialize)]
+struct AylaErrorBody {
+    error: Option<serde_json::Value>,
+    errors: Option<serde_json::Value>,
+}
+
+impl ApiError {
+    pub(crate) async fn from_response(res: Response) -> Self {
+        let status = res.status();
+        let body = res.text().await.unwrap_or_default();
+        let message = match serde_json::from_str::<AylaErrorBody>(&body)
+            .ok()
+            .and_then(|b| b.error.or(b.errors))
+        {
+            Some(e) => flatten_messages(&e).join("; "),
+            None => body,
+        };
+
+        Self { status, message }
+    }
+
+    /// Whether the request may succeed if it is tried again later
+    pub fn is_retryable(&self) -> bool {
+        self.status == StatusCode::TOO_MANY_REQUESTS
+            || self.status == StatusCode::REQUEST_TIMEOUT
+            || self.status.is_server_error()
+    }
+
+    /// Whether the request was rejected because of the account credentials or session
+    pub fn is_auth_error(&self) -> bool {
+        self.status == StatusCode::UNAUTHORIZED || self.status == StatusCode::FORBIDDEN
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "status code {} -- {}", self.status, self.message)
+    }
+}
+
+fn flatten_messages(value: &serde_json::Value) -> Vec<String> {
+    match value {
+        serde_json::Value::String(s) => vec![s.clone()],
+        serde_json::Value::Array(a) => a.iter().flat_map(flatten_messages).collect(),
+        serde_json::Value::Object(o) => o
+            .iter()
+            .flat_map(|(field, v)| {
+                flatten_messages(v)
+                    .into_iter()
+                    .map(move |m| format!("{} {}", field, m))
+            })
+            .collect(),
+        v => vec![v.to_string()],
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum AylaError {
-    #[error("Failed to login: status code {0} -- {1}")]
-    LoginError(StatusCode, String),
-    #[error("Failed to refresh token: status code {0} -- {1}")]
-    RefreshTokenError(StatusCode, String),
-    #[error("Failed to logout: status code {0} -- {1}")]
-    LogoutError(StatusCode, String),
+    #[error("Failed to login: {0}")]
+    LoginError(ApiError),
+    #[error("Failed to refresh token: {0}")]
+    RefreshTokenError(ApiError),
+    #[error("Failed to logout: {0}")]
+    LogoutError(ApiError),
+    /// Ayla asked us to wait longer than the retry policy allows before sending more requests
+    #[error("Rate limited: {0}")]
+    RateLimited(ApiError),
     #[error("reqwest error")]
     ReqwestError(#[from] reqwest::Error),
 }
 
+impl AylaError {
+    pub fn api_error(&self) -> Option<&ApiError> {
+        match self {
+            Self::LoginError(e)
+            | Self::RefreshTokenError(e)
+            | Self::LogoutError(e)
+            | Self::RateLimited(e) => Some(e),
+            Self::ReqwestError(_) => None,
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::ReqwestError(e) => is_retryable_reqwest(e),
+            e => e.api_error().is_some_and(ApiError::is_retryable),
+        }
+    }
+
+    pub fn is_auth_error(&self) -> bool {
+        self.api_error().is_some_and(ApiError::is_auth_error)
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum SharkError {
     #[error("Ayla Client error: {0}")]
     AylaError(#[from] AylaError),
-    #[error("Shark API error: status code {0} -- {1}")]
-    ApiError(StatusCode, String),
+    #[error("Shark API error: {0}")]
+    ApiError(ApiError),
     #[error("reqwest error: {0}")]
     ReqwestError(#[from] reqwest::Error),
+    #[error("invalid url: {0}")]
+    InvalidUrl(String),
+    #[error("invalid robot state: {0}")]
+    InvalidState(String),
+    #[error("unknown room: {0}")]
+    UnknownRoom(String),
+    #[error("invalid property name: {0}")]
+    InvalidPropertyName(String),
+    #[error("unknown device: {name}, did you mean one of: {}", .suggestions.join(", "))]
+    UnknownDevice {
+        name: String,
+        /// Names of devices that are similar to the one asked for
+        suggestions: Vec<String>,
+    },
+    #[error("ambiguous device name: {name} matches {}", .dsns.join(", "))]
+    AmbiguousDevice {
+        name: String,
+        /// DSNs of all devices with this name
+        dsns: Vec<String>,
+    },
+    #[error("unexpected property value: {0}")]
+    PropertyValueError(#[from] serde_json::Error),
+    #[error("LAN mode error: {0}")]
+    LanError(String),
+    #[error("failed to start runtime: {0}")]
+    RuntimeError(std::io::Error),
+}
+
+impl SharkError {
+    /// The Ayla error response that caused this error, if any
+    pub fn api_error(&self) -> Option<&ApiError> {
+        match self {
+            Self::AylaError(e) => e.api_error(),
+            Self::ApiError(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// Whether the request may succeed if it is tried again later
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::AylaError(e) => e.is_retryable(),
+            Self::ApiError(e) => e.is_retryable(),
+            Self::ReqwestError(e) => is_retryable_reqwest(e),
+            _ => false,
+        }
+    }
+
+    /// Whether the request was rejected because of the account credentials or session
+    pub fn is_auth_error(&self) -> bool {
+        self.api_error().is_some_and(ApiError::is_auth_error)
+    }
+}
+
+fn is_retryable_reqwest(e: &reqwest::Error) -> bool {
+    e.is_timeout() || e.is_connect()
 }
@@ -0,0 +1,504 @@
+//! Ayla's LAN mode, which controls a robot over the local network instead of through the cloud.
+//!
+//! The client registers with a robot by posting the address of its own HTTP server to the
+//! robot's `local_reg.json`. The robot then connects back to that server to exchange session
+//! keys, which are derived from the device's `lanip_key`, and to fetch queued commands. Messages
+//! are encrypted with AES-256-CBC and signed with HMAC-SHA256.
+
+use crate::error::SharkError;
+use crate::models::AylaLanKey;
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::server::conn::AddrStream;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use openssl::symm::{Cipher, Crypter, Mode};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::oneshot;
+
+/// Prefix of the paths the robot requests from the client's server
+const LAN_URI: &str = "/local_lan";
+
+/// How commands are sent to a robot
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    /// Through the Ayla cloud
+    Cloud,
+    /// Directly over the local network, falling back to the cloud when the robot can't be
+    /// reached
+    Lan,
+}
+
+/// How the client talks to robots on the local network.
+#[derive(Debug, Clone)]
+pub struct LanConfig {
+    /// Address of the HTTP server robots connect back to
+    pub listen: SocketAddr,
+    /// Port of the robots' own HTTP server
+    pub device_port: u16,
+    /// How long a robot has to pick up a command before it is sent through the cloud instead
+    pub timeout: Duration,
+}
+
+impl Default for LanConfig {
+    fn default() -> Self {
+        Self {
+            listen: SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 10275),
+            device_port: 80,
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Commands to a robot in LAN mode that were sent through the cloud instead
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq, JsonSchema)]
+pub struct LanStats {
+    /// Number of commands the robot couldn't be sent over the local network
+    pub fallbacks: u64,
+    /// Why the most recent command couldn't be sent over the local network
+    pub last_error: Option<String>,
+}
+
+/// The keys protecting one direction of a session
+struct Keys {
+    sign: Vec<u8>,
+    crypto: Vec<u8>,
+    /// Each message is encrypted with the last ciphertext block of the previous one as its IV
+    iv: Vec<u8>,
+}
+
+impl Keys {
+    fn derive(lan_key: &[u8], seed: &str) -> Result<Self, ErrorStack> {
+        Ok(Self {
+            sign: derive_key(lan_key, seed, "0")?,
+            crypto: derive_key(lan_key, seed, "1")?,
+            iv: derive_key(lan_key, seed, "2")?[..16].to_vec(),
+        })
+    }
+
+    /// Encrypt and sign `plaintext` as `{"enc": ..., "sign": ...}`
+    fn seal(&mut self, plaintext: &[u8]) -> Result<Value, ErrorStack> {
+        let mut padded = plaintext.to_vec();
+        padded.resize(plaintext.len().div_ceil(16) * 16, 0);
+        let enc = self.crypt(Mode::Encrypt, &padded)?;
+        self.iv = enc[enc.len() - 16..].to_vec();
+
+        Ok(json!({
+            "enc": openssl::base64::encode_block(&enc),
+            "sign": openssl::base64::encode_block(&hmac(&self.sign, plaintext)?),
+        }))
+    }
+
+    /// Decrypt a message sealed by the other side, `None` if it wasn't signed with our keys
+    fn open(&mut self, enc: &str, sign: &str) -> Result<Option<Vec<u8>>, ErrorStack> {
+        let enc = openssl::base64::decode_block(enc)?;
+        let sign = openssl::base64::decode_block(sign)?;
+        if enc.is_empty() || enc.len() % 16 != 0 {
+            return Ok(None);
+        }
+
+        let mut plaintext = self.crypt(Mode::Decrypt, &enc)?;
+        self.iv = enc[enc.len() - 16..].to_vec();
+        while plaintext.last() == Some(&0) {
+            plaintext.pop();
+        }
+
+        let expected = hmac(&self.sign, &plaintext)?;
+        if expected.len() != sign.len() || !openssl::memcmp::eq(&expected, &sign) {
+            return Ok(None);
+        }
+        Ok(Some(plaintext))
+    }
+
+    fn crypt(&self, mode: Mode, data: &[u8]) -> Result<Vec<u8>, ErrorStack> {
+        let cipher = Cipher::aes_256_cbc();
+        let mut crypter = Crypter::new(cipher, mode, &self.crypto, Some(&self.iv))?;
+        crypter.pad(false);
+        let mut out = vec![0; data.len() + cipher.block_size()];
+        let n = crypter.update(data, &mut out)?;
+        let n = n + crypter.finalize(&mut out[n..])?;
+        out.truncate(n);
+        Ok(out)
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Result<Vec<u8>, ErrorStack> {
+    let key = PKey::hmac(key)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    signer.update(data)?;
+    signer.sign_to_vec()
+}
+
+fn derive_key(lan_key: &[u8], seed: &str, suffix: &str) -> Result<Vec<u8>, ErrorStack> {
+    let seed = format!("{}{}", seed, suffix);
+    let inner = hmac(lan_key, seed.as_bytes())?;
+    hmac(lan_key, &[inner.as_slice(), seed.as_bytes()].concat())
+}
+
+/// The keys negotiated with a robot
+struct Session {
+    /// Protects messages from the client to the robot
+    app: Keys,
+    /// Protects messages from the robot to the client
+    dev: Keys,
+    seq_no: u64,
+}
+
+struct Command {
+    id: u64,
+    data: Value,
+    /// Notified once the robot fetched the command
+    sent: oneshot::Sender<()>,
+}
+
+struct LanDevice {
+    dsn: String,
+    key: AylaLanKey,
+    session: Option<Session>,
+    commands: VecDeque<Command>,
+    next_id: u64,
+    stats: LanStats,
+}
+
+#[derive(Deserialize)]
+struct KeyExchangeRequest {
+    key_exchange: KeyExchange,
+}
+
+#[derive(Deserialize)]
+struct KeyExchange {
+    random_1: String,
+    time_1: u64,
+    key_id: i64,
+}
+
+#[derive(Deserialize)]
+struct SealedMessage {
+    enc: String,
+    sign: String,
+}
+
+type LanResponse = Result<Response<Body>, StatusCode>;
+
+impl LanDevice {
+    fn key_exchange(&mut self, body: &[u8]) -> LanResponse {
+        let req: KeyExchangeRequest =
+            serde_json::from_slice(body).map_err(|_| StatusCode::BAD_REQUEST)?;
+        let req = req.key_exchange;
+        if req.key_id != self.key.lanip_key_id {
+            return Err(StatusCode::PRECONDITION_FAILED);
+        }
+
+        let random_2: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(16)
+            .map(char::from)
+            .collect();
+        let time_2 = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+
+        let key = self.key.lanip_key.as_bytes();
+        let app = format!("{}{}{}{}", req.random_1, random_2, req.time_1, time_2);
+        let dev = format!("{}{}{}{}", random_2, req.random_1, time_2, req.time_1);
+        self.session = Some(Session {
+            app: Keys::derive(key, &app).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+            dev: Keys::derive(key, &dev).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+            seq_no: 0,
+        });
+
+        Ok(json_response(
+            StatusCode::OK,
+            &json!({ "random_2": random_2, "time_2": time_2 }),
+        ))
+    }
+
+    /// Hand the next queued command to the robot, a 206 tells it that more are waiting
+    fn next_command(&mut self) -> LanResponse {
+        let session = self
+            .session
+            .as_mut()
+            .ok_or(StatusCode::PRECONDITION_FAILED)?;
+        let command = self.commands.pop_front();
+        let data = command
+            .as_ref()
+            .map_or_else(|| json!({}), |c| c.data.clone());
+
+        let message = json!({ "seq_no": session.seq_no, "data": data });
+        session.seq_no += 1;
+        let sealed = session
+            .app
+            .seal(message.to_string().as_bytes())
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        if let Some(command) = command {
+            let _ = command.sent.send(());
+        }
+        let status = if self.commands.is_empty() {
+            StatusCode::OK
+        } else {
+            StatusCode::PARTIAL_CONTENT
+        };
+        Ok(json_response(status, &sealed))
+    }
+
+    /// Accept a property update pushed by the robot. Only the signature is checked since the
+    /// cloud remains the source of truth for reads.
+    fn receive(&mut self, body: &[u8]) -> LanResponse {
+        let session = self
+            .session
+            .as_mut()
+            .ok_or(StatusCode::PRECONDITION_FAILED)?;
+        let message: SealedMessage =
+            serde_json::from_slice(body).map_err(|_| StatusCode::BAD_REQUEST)?;
+        match session.dev.open(&message.enc, &message.sign) {
+            Ok(Some(_)) => Ok(json_response(StatusCode::OK, &json!({}))),
+            _ => Err(StatusCode::BAD_REQUEST),
+        }
+    }
+}
+
+fn json_response(status: StatusCode, body: &Value) -> Response<Body> {
+    let mut res = Response::new(Body::from(body.to_string()));
+    *res.status_mut() = status;
+    res.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    res
+}
+
+type Devices = Arc<Mutex<HashMap<IpAddr, LanDevice>>>;
+
+async fn handle(devices: Devices, remote: IpAddr, req: Request<Body>) -> Response<Body> {
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let body = match hyper::body::to_bytes(req.into_body()).await {
+        Ok(body) => body,
+        Err(_) => return json_response(StatusCode::BAD_REQUEST, &json!({})),
+    };
+
+    let mut devices = devices.lock().unwrap();
+    // Only robots that were switched to LAN mode may talk to us
+    let device = match devices.get_mut(&remote) {
+        Some(device) => device,
+        None => return json_response(StatusCode::FORBIDDEN, &json!({})),
+    };
+
+    let res = match (method, path.strip_prefix(LAN_URI)) {
+        (Method::POST, Some("/key_exchange.json")) => device.key_exchange(&body),
+        (Method::GET, Some("/commands.json")) => device.next_command(),
+        (Method::POST, Some(p)) if p.starts_with("/property/") => device.receive(&body),
+        _ => Err(StatusCode::NOT_FOUND),
+    };
+    res.unwrap_or_else(|status| json_response(status, &json!({})))
+}
+
+/// Sends commands to robots over the local network.
+pub(crate) struct LanClient {
+    config: LanConfig,
+    /// The address our server is actually listening on
+    local_addr: SocketAddr,
+    http: reqwest::Client,
+    devices: Devices,
+    /// Stops the server when the client is dropped
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl LanClient {
+    /// Start the server robots connect back to
+    pub(crate) fn start(config: LanConfig) -> Result<Self, SharkError> {
+        let devices: Devices = Arc::new(Mutex::new(HashMap::new()));
+
+        let svc_devices = Arc::clone(&devices);
+        let make_svc = make_service_fn(move |conn: &AddrStream| {
+            let devices = Arc::clone(&svc_devices);
+            let remote = conn.remote_addr().ip();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let devices = Arc::clone(&devices);
+                    async move { Ok::<_, Infallible>(handle(devices, remote, req).await) }
+                }))
+            }
+        });
+
+        let server = Server::try_bind(&config.listen)
+            .map_err(|e| {
+                SharkError::LanError(format!("failed to listen on {}: {}", config.listen, e))
+            })?
+            .serve(make_svc);
+        let local_addr = server.local_addr();
+        let (shutdown, stop) = oneshot::channel::<()>();
+        tokio::spawn(server.with_graceful_shutdown(async {
+            let _ = stop.await;
+        }));
+
+        Ok(Self {
+            http: reqwest::Client::builder().timeout(config.timeout).build()?,
+            config,
+            local_addr,
+            devices,
+            _shutdown: shutdown,
+        })
+    }
+
+    /// Control the robot `dsn` at `ip` over the local network from now on
+    pub(crate) fn add_device(&self, dsn: &str, ip: IpAddr, key: AylaLanKey) {
+        let mut devices = self.devices.lock().unwrap();
+        devices.retain(|_, d| d.dsn != dsn);
+        devices.insert(
+            ip,
+            LanDevice {
+                dsn: dsn.to_string(),
+                key,
+                session: None,
+                commands: VecDeque::new(),
+                next_id: 0,
+                stats: LanStats::default(),
+            },
+        );
+    }
+
+    pub(crate) fn remove_device(&self, dsn: &str) {
+        self.devices.lock().unwrap().retain(|_, d| d.dsn != dsn);
+    }
+
+    pub(crate) fn has_device(&self, dsn: &str) -> bool {
+        self.devices.lock().unwrap().values().any(|d| d.dsn == dsn)
+    }
+
+    pub(crate) fn stats(&self, dsn: &str) -> Option<LanStats> {
+        let devices = self.devices.lock().unwrap();
+        devices
+            .values()
+            .find(|d| d.dsn == dsn)
+            .map(|d| d.stats.clone())
+    }
+
+    /// Remember that a command to the robot is sent through the cloud because of `error`
+    pub(crate) fn record_fallback(&self, dsn: &str, error: &SharkError) {
+        let mut devices = self.devices.lock().unwrap();
+        if let Some(device) = devices.values_mut().find(|d| d.dsn == dsn) {
+            device.stats.fallbacks += 1;
+            device.stats.last_error = Some(error.to_string());
+        }
+    }
+
+    /// Queue a property update for the robot and wait for the robot to fetch it
+    pub(crate) async fn set_property(
+        &self,
+        dsn: &str,
+        name: &str,
+        value: Value,
+    ) -> Result<(), SharkError> {
+        let data = json!({ "properties": [{ "property": {
+            "base_type": base_type(&value),
+            "name": name,
+            "value": value,
+        }}]});
+
+        let (ip, id, sent, has_session) = {
+            let mut devices = self.devices.lock().unwrap();
+            let (ip, device) = devices
+                .iter_mut()
+                .find(|(_, d)| d.dsn == dsn)
+                .ok_or_else(|| SharkError::LanError(format!("{} is not in LAN mode", dsn)))?;
+            let (tx, rx) = oneshot::channel();
+            let id = device.next_id;
+            device.next_id += 1;
+            device.commands.push_back(Command { id, data, sent: tx });
+            (*ip, id, rx, device.session.is_some())
+        };
+
+        let res = match self.register(ip, has_session).await {
+            Ok(()) => tokio::time::timeout(self.config.timeout, sent)
+                .await
+                .map_err(|_| SharkError::LanError(format!("{} did not fetch the command", dsn)))
+                .and_then(|r| r.map_err(|_| SharkError::LanError("command dropped".to_string()))),
+            Err(e) => Err(e),
+        };
+
+        if res.is_err() {
+            let mut devices = self.devices.lock().unwrap();
+            if let Some(device) = devices.get_mut(&ip) {
+                let queued = device.commands.len();
+                device.commands.retain(|c| c.id != id);
+                // The robot picked the command up after all
+                if device.commands.len() == queued {
+                    return Ok(());
+                }
+            }
+        }
+        res
+    }
+
+    /// Ask the robot to connect to our server. An existing session is refreshed with a PUT,
+    /// otherwise the robot starts a new key exchange.
+    async fn register(&self, ip: IpAddr, has_session: bool) -> Result<(), SharkError> {
+        let local_ip = match self.local_addr.ip() {
+            ip if !ip.is_unspecified() => ip,
+            _ => local_ip_for(ip)?,
+        };
+        let body = json!({ "local_reg": {
+            "uri": LAN_URI,
+            "ip": local_ip.to_string(),
+            "port": self.local_addr.port(),
+            "notify": 1,
+        }});
+        let url = format!(
+            "http://{}/local_reg.json",
+            SocketAddr::new(ip, self.config.device_port)
+        );
+
+        if has_session {
+            let res = self.http.put(&url).json(&body).send().await?;
+            if res.status().is_success() {
+                return Ok(());
+            }
+        }
+
+        let res = self.http.post(&url).json(&body).send().await?;
+        if !res.status().is_success() {
+            return Err(SharkError::LanError(format!(
+                "robot rejected registration with {}",
+                res.status()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// The address of this host on the interface that reaches `device`
+fn local_ip_for(device: IpAddr) -> Result<IpAddr, SharkError> {
+    let unspecified = match device {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(std::net::Ipv6Addr::UNSPECIFIED),
+    };
+    // Connecting a UDP socket sends nothing but selects the route to the device
+    let socket = UdpSocket::bind(SocketAddr::new(unspecified, 0))
+        .and_then(|s| s.connect(SocketAddr::new(device, 80)).map(|_| s))
+        .and_then(|s| s.local_addr())
+        .map_err(|e| SharkError::LanError(format!("no route to {}: {}", device, e)))?;
+    Ok(socket.ip())
+}
+
+fn base_type(value: &Value) -> &'static str {
+    match value {
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "decimal",
+        Value::Number(_) => "integer",
+        _ => "string",
+    }
+}
@@ -1,25 +1,50 @@
 use crate::ayla::RequestType;
 use crate::region::Region;
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures::stream::{self, Stream};
+pub use reqwest::Url;
 use reqwest::{Method, Response};
 use schemars::JsonSchema;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::json;
-use serde_repr::*;
+use std::collections::VecDeque;
+use std::net::IpAddr;
+use std::time::Duration;
 
 mod ayla;
+pub mod blocking;
+mod cache;
 pub mod error;
+pub mod lan;
+mod lookup;
 mod models;
+pub mod properties;
 pub mod region;
-pub use error::SharkError;
+pub mod retry;
+pub mod token_store;
+pub mod watch;
+pub use error::{ApiError, SharkError};
+pub use lan::{LanConfig, LanStats, Transport};
+pub use lookup::find_device;
+pub use properties::{
+    ConsumableStatus, Datapoint, SharkDeviceProperties, SharkDeviceStatus, SharkMaintenance,
+};
+pub use retry::RetryPolicy;
+pub use token_store::{FileTokenStore, SharkTokens, TokenStore};
+pub use watch::{PropertyChange, SharkEvent};
 
 pub type Result<T> = std::result::Result<T, error::SharkError>;
 
+/// How long a request to the Ayla cloud may take unless configured otherwise
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
 #[derive(Deserialize)]
 struct GetDevicesResponse {
     device: SharkDevice,
 }
 
-#[derive(Serialize_repr)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
 #[repr(u8)]
 pub enum OperatingMode {
     Stop = 0,
@@ -28,6 +53,51 @@ pub enum OperatingMode {
     Return = 3,
 }
 
+impl OperatingMode {
+    /// Convert the value of an Ayla datapoint into an `OperatingMode`
+    pub(crate) fn from_value(value: i64) -> Option<Self> {
+        match value {
+            0 => Some(Self::Stop),
+            1 => Some(Self::Pause),
+            2 => Some(Self::Start),
+            3 => Some(Self::Return),
+            _ => None,
+        }
+    }
+}
+
+/// Suction power used while cleaning
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+#[repr(u8)]
+pub enum PowerMode {
+    Normal = 0,
+    Eco = 1,
+    Max = 2,
+}
+
+impl PowerMode {
+    /// Convert the value of an Ayla datapoint into a `PowerMode`
+    pub(crate) fn from_value(value: i64) -> Option<Self> {
+        match value {
+            0 => Some(Self::Normal),
+            1 => Some(Self::Eco),
+            2 => Some(Self::Max),
+            _ => None,
+        }
+    }
+}
+
+/// Whether a device is connected to the Ayla cloud
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, JsonSchema)]
+pub enum ConnectionStatus {
+    Online,
+    Offline,
+    Initializing,
+    #[serde(other)]
+    Unknown,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 pub struct SharkDevice {
     pub dsn: String,
@@ -36,14 +106,37 @@ pub struct SharkDevice {
     pub mac: String,
     pub product_name: String,
     pub key: u64,
+    pub connection_status: Option<ConnectionStatus>,
+    /// When the device last connected to the Ayla cloud
+    pub connected_at: Option<DateTime<Utc>>,
+    /// Address of the device on the local network
+    pub lan_ip: Option<IpAddr>,
+    pub lan_enabled: Option<bool>,
+    /// Version of the device's Ayla module firmware
+    pub sw_version: Option<String>,
+    pub device_type: Option<String>,
     #[serde(flatten)]
     pub extra: std::collections::HashMap<String, serde_json::Value>,
 }
 
+impl SharkDevice {
+    pub fn is_online(&self) -> bool {
+        self.connection_status == Some(ConnectionStatus::Online)
+    }
+}
+
 pub struct SharkClientBuilder {
     email: String,
     password: String,
     region: Region,
+    user_url: Option<Url>,
+    device_url: Option<Url>,
+    token_store: Option<Box<dyn TokenStore>>,
+    timeout: Duration,
+    retry_policy: RetryPolicy,
+    min_request_interval: Duration,
+    cache_ttl: Duration,
+    lan: Option<LanConfig>,
 }
 
 impl SharkClientBuilder {
@@ -52,6 +145,14 @@ impl SharkClientBuilder {
             email,
             password,
             region: Region::Us,
+            user_url: None,
+            device_url: None,
+            token_store: None,
+            timeout: DEFAULT_TIMEOUT,
+            retry_policy: RetryPolicy::default(),
+            min_request_interval: Duration::ZERO,
+            cache_ttl: cache::DEFAULT_TTL,
+            lan: None,
         }
     }
 
@@ -60,14 +161,67 @@ impl SharkClientBuilder {
         self
     }
 
-    /// Given the provided credentials attempt to login to the Shark API service
+    /// Override the Ayla user service URL of the selected region
+    pub fn user_url(mut self, url: Url) -> Self {
+        self.user_url = Some(url);
+        self
+    }
+
+    /// Override the Ayla device service URL of the selected region
+    pub fn device_url(mut self, url: Url) -> Self {
+        self.device_url = Some(url);
+        self
+    }
+
+    /// Persist session tokens in `store` so they can be reused instead of signing in again
+    pub fn token_store<S: TokenStore + 'static>(mut self, store: S) -> Self {
+        self.token_store = Some(Box::new(store));
+        self
+    }
+
+    /// Fail requests to the Ayla cloud that take longer than `timeout`, defaults to 30 seconds
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set how failed requests are retried, defaults to `RetryPolicy::default()`
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Wait at least `interval` between requests to the Ayla cloud
+    pub fn rate_limit(mut self, interval: Duration) -> Self {
+        self.min_request_interval = interval;
+        self
+    }
+
+    /// Reuse device lists and properties fetched from the Ayla cloud for up to `ttl`, defaults to
+    /// 30 seconds. A zero `ttl` disables caching.
+    pub fn cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = ttl;
+        self
+    }
+
+    /// Enable Ayla's LAN mode so robots switched to `Transport::Lan` are controlled over the
+    /// local network
+    pub fn lan(mut self, config: LanConfig) -> Self {
+        self.lan = Some(config);
+        self
+    }
+
+    /// Given the provided credentials attempt to login to the Shark API service, reusing any
+    /// tokens found in the token store
     pub async fn build(self) -> Result<SharkClient> {
-        SharkClient::from_creds(self.region, self.email, self.password).await
+        SharkClient::from_builder(self).await
     }
 }
 
 pub struct SharkClient {
     ayla: ayla::AylaClient,
+    cache: cache::SharkCache,
+    lan: Option<lan::LanClient>,
 }
 
 impl SharkClient {
@@ -79,16 +233,48 @@ impl SharkClient {
         SharkClientBuilder::new(email.into(), password.into())
     }
 
-    async fn from_creds(region: Region, email: String, password: String) -> Result<Self> {
-        let ayla = ayla::AylaClient::new(region, email, password);
+    async fn from_builder(builder: SharkClientBuilder) -> Result<Self> {
+        let region = builder.region;
+        let user_url = match builder.user_url {
+            Some(url) => url,
+            None => parse_url(region.user_url())?,
+        };
+        let device_url = match builder.device_url {
+            Some(url) => url,
+            None => parse_url(region.device_url())?,
+        };
+        let ayla = ayla::AylaClient::new(ayla::AylaConfig {
+            region,
+            user_url,
+            device_url,
+            email: builder.email,
+            password: builder.password,
+            token_store: builder.token_store,
+            timeout: builder.timeout,
+            retry_policy: builder.retry_policy,
+            min_request_interval: builder.min_request_interval,
+        });
 
-        let mut sharkvac = Self { ayla };
+        let sharkvac = Self {
+            ayla,
+            cache: cache::SharkCache::new(builder.cache_ttl),
+            lan: builder.lan.map(lan::LanClient::start).transpose()?,
+        };
         sharkvac.ayla.sign_in().await?;
 
         Ok(sharkvac)
     }
 
+    /// Get all devices on the account, reusing a recently fetched list
     pub async fn get_devices(&self) -> Result<Vec<SharkDevice>> {
+        match self.cache.devices.get(&()) {
+            Some(devices) => Ok(devices),
+            None => self.refresh_devices().await,
+        }
+    }
+
+    /// Fetch all devices on the account from the Ayla cloud, bypassing the cache
+    pub async fn refresh_devices(&self) -> Result<Vec<SharkDevice>> {
         let req = self.ayla.request(
             RequestType::Device,
             Method::GET,
@@ -97,14 +283,67 @@ impl SharkClient {
         )?;
 
         let res = self.ayla.execute(req).await?;
-        Ok(get_api_response::<Vec<GetDevicesResponse>>(res)
+        let devices: Vec<SharkDevice> = get_api_response::<Vec<GetDevicesResponse>>(res)
             .await?
             .into_iter()
             .map(|v| v.device)
-            .collect())
+            .collect();
+
+        self.cache.devices.insert((), devices.clone());
+        Ok(devices)
+    }
+
+    /// Resolve the DSN of a device given either its DSN or its user-assigned name, ignoring case.
+    ///
+    /// Names are looked up in a recently fetched device list, which is fetched again before
+    /// giving up on a name it doesn't contain in case the device was added or renamed since.
+    pub async fn resolve_device(&self, name: &str) -> Result<String> {
+        let cached = self
+            .cache
+            .devices
+            .get(&())
+            .map(|devices| lookup::find_device(&devices, name));
+
+        match cached {
+            Some(Err(SharkError::UnknownDevice { .. })) | None => {
+                lookup::find_device(&self.refresh_devices().await?, name)
+            }
+            Some(res) => res,
+        }
+    }
+
+    pub async fn get_device(&self, dsn: &str) -> Result<SharkDevice> {
+        match self.cache.device.get(&dsn.to_string()) {
+            Some(device) => Ok(device),
+            None => self.refresh_device(dsn).await,
+        }
+    }
+
+    /// Fetch the device from the Ayla cloud, bypassing the cache
+    pub async fn refresh_device(&self, dsn: &str) -> Result<SharkDevice> {
+        let req = self.ayla.request(
+            RequestType::Device,
+            Method::GET,
+            format!("/apiv1/dsns/{}", dsn),
+            None::<()>,
+        )?;
+
+        let res = self.ayla.execute(req).await?;
+        let device = get_api_response::<GetDevicesResponse>(res).await?.device;
+        self.cache.device.insert(dsn.to_string(), device.clone());
+        Ok(device)
+    }
+
+    /// Get all of the device's properties, reusing recently fetched ones
+    pub async fn get_device_properties(&self, dsn: &str) -> Result<SharkDeviceProperties> {
+        match self.cache.properties.get(&dsn.to_string()) {
+            Some(properties) => Ok(properties),
+            None => self.refresh_device_properties(dsn).await,
+        }
     }
 
-    pub async fn get_device_properties(&self, dsn: &str) -> Result<()> {
+    /// Fetch all of the device's properties from the Ayla cloud, bypassing the cache
+    pub async fn refresh_device_properties(&self, dsn: &str) -> Result<SharkDeviceProperties> {
         let req = self.ayla.request(
             RequestType::Device,
             Method::GET,
@@ -113,42 +352,351 @@ impl SharkClient {
         )?;
 
         let res = self.ayla.execute(req).await?;
-        let properties = get_api_response::<serde_json::Value>(res).await?;
-        println!("{:#?}", properties);
+        let properties = get_api_response::<Vec<models::AylaPropertyResponse>>(res)
+            .await?
+            .into_iter()
+            .map(|v| v.property)
+            .collect();
 
-        Ok(())
+        let properties = SharkDeviceProperties::from_properties(properties);
+        self.cache
+            .properties
+            .insert(dsn.to_string(), properties.clone());
+        Ok(properties)
     }
 
-    pub async fn set_device_operating_mode(&self, dsn: &str, mode: OperatingMode) -> Result<()> {
-        let body = json!({ "datapoint": { "value": mode }});
+    /// Forget all cached devices and properties
+    pub fn invalidate_cache(&self) {
+        self.cache.clear();
+    }
+
+    /// Get a summary of the device's battery, operating mode, charging and connection state
+    pub async fn get_device_status(&self, dsn: &str) -> Result<SharkDeviceStatus> {
+        let device = self.get_device(dsn).await?;
+        let properties = self.get_device_properties(dsn).await?;
+        Ok(SharkDeviceStatus::new(&device, &properties))
+    }
+
+    /// Read the current value of the Ayla property `name`
+    pub async fn get_property<T>(&self, dsn: &str, name: &str) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        check_property_name(name)?;
+        let req = self.ayla.request(
+            RequestType::Device,
+            Method::GET,
+            format!("/apiv1/dsns/{}/properties/{}", dsn, name),
+            None::<()>,
+        )?;
+
+        let res = self.ayla.execute(req).await?;
+        let property = get_api_response::<models::AylaPropertyResponse>(res)
+            .await?
+            .property;
+        Ok(serde_json::from_value(property.value)?)
+    }
+
+    /// Get the values reported for the Ayla property `name`, oldest first. Only values reported
+    /// since `since` are returned if given, and at most `limit` of them.
+    pub async fn get_property_history<T>(
+        &self,
+        dsn: &str,
+        name: &str,
+        since: Option<DateTime<Utc>>,
+        limit: Option<u32>,
+    ) -> Result<Vec<Datapoint<T>>>
+    where
+        T: DeserializeOwned,
+    {
+        check_property_name(name)?;
+        let mut req = self.ayla.request(
+            RequestType::Device,
+            Method::GET,
+            format!("/apiv1/dsns/{}/properties/{}/datapoints.json", dsn, name),
+            None::<()>,
+        )?;
+        {
+            let mut query = req.url_mut().query_pairs_mut();
+            if let Some(since) = since {
+                query.append_pair(
+                    "filter[created_at_since_date]",
+                    &since.to_rfc3339_opts(SecondsFormat::Secs, true),
+                );
+            }
+            if let Some(limit) = limit {
+                query.append_pair("limit", &limit.to_string());
+            }
+        }
+
+        let res = self.ayla.execute(req).await?;
+        let mut history: Vec<Datapoint<T>> =
+            get_api_response::<Vec<models::AylaDatapointResponse<T>>>(res)
+                .await?
+                .into_iter()
+                .map(|v| v.datapoint)
+                .collect();
+        history.sort_by_key(|d| d.created_at);
+        Ok(history)
+    }
+
+    /// Write a new datapoint to the Ayla property `name`. The device's cached properties are
+    /// discarded since the write may change them.
+    ///
+    /// Robots switched to `Transport::Lan` are sent the datapoint over the local network, and
+    /// through the cloud only if they can't be reached. Such fallbacks are counted in
+    /// `lan_stats`.
+    pub async fn set_property<T>(&self, dsn: &str, name: &str, value: T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        check_property_name(name)?;
+        let value = serde_json::to_value(value)?;
+        if let Some(lan) = self.lan.as_ref().filter(|lan| lan.has_device(dsn)) {
+            match lan.set_property(dsn, name, value.clone()).await {
+                Ok(()) => {
+                    self.cache.invalidate(dsn);
+                    return Ok(());
+                }
+                // The robot may only be asleep or briefly off the network, so the command still
+                // goes through the cloud. The error is kept in the robot's `LanStats` instead of
+                // failing the command.
+                Err(e) => lan.record_fallback(dsn, &e),
+            }
+        }
+
+        let body = json!({ "datapoint": { "value": value }});
         let req = self.ayla.request(
             RequestType::Device,
             Method::POST,
-            format!(
-                "/apiv1/dsns/{}/properties/SET_Operating_Mode/datapoints",
-                dsn
-            ),
+            format!("/apiv1/dsns/{}/properties/{}/datapoints", dsn, name),
             Some(body),
         )?;
 
+        let res = self.ayla.execute(req).await;
+        // Even a failed write may have reached the device
+        self.cache.invalidate(dsn);
+        let _ = get_api_response::<serde_json::Value>(res?).await?;
+        Ok(())
+    }
+
+    /// Select how commands are sent to the device. Switching to `Transport::Lan` fetches the
+    /// device's address and LAN key from the cloud, so it has to be done while online.
+    pub async fn set_transport(&self, dsn: &str, transport: Transport) -> Result<()> {
+        let lan = match (transport, &self.lan) {
+            (Transport::Cloud, Some(lan)) => {
+                lan.remove_device(dsn);
+                return Ok(());
+            }
+            (Transport::Cloud, None) => return Ok(()),
+            (Transport::Lan, Some(lan)) => lan,
+            (Transport::Lan, None) => {
+                return Err(SharkError::LanError(
+                    "LAN mode is not enabled for this client".to_string(),
+                ))
+            }
+        };
+
+        let device = self.refresh_device(dsn).await?;
+        let ip = match (device.lan_enabled, device.lan_ip) {
+            (Some(true), Some(ip)) => ip,
+            _ => {
+                return Err(SharkError::LanError(format!(
+                    "{} does not support LAN mode",
+                    dsn
+                )))
+            }
+        };
+
+        let req = self.ayla.request(
+            RequestType::Device,
+            Method::GET,
+            format!("/apiv1/dsns/{}/lan.json", dsn),
+            None::<()>,
+        )?;
         let res = self.ayla.execute(req).await?;
-        let _ = get_api_response::<serde_json::Value>(res).await?;
+        let key = get_api_response::<models::AylaLanResponse>(res)
+            .await?
+            .lanip;
+
+        lan.add_device(dsn, ip, key);
         Ok(())
     }
 
-    /// Refresh API token (expires after 24h)
-    pub async fn refresh_token(&mut self) -> Result<()> {
+    /// How commands are currently sent to the device
+    pub fn transport(&self, dsn: &str) -> Transport {
+        match &self.lan {
+            Some(lan) if lan.has_device(dsn) => Transport::Lan,
+            _ => Transport::Cloud,
+        }
+    }
+
+    /// How often commands to the device were sent through the cloud because it couldn't be
+    /// reached over the local network, `None` unless it uses `Transport::Lan`
+    pub fn lan_stats(&self, dsn: &str) -> Option<LanStats> {
+        self.lan.as_ref()?.stats(dsn)
+    }
+
+    /// Get the remaining life of the device's brushes and filter
+    pub async fn get_maintenance(&self, dsn: &str) -> Result<SharkMaintenance> {
+        let properties = self.get_device_properties(dsn).await?;
+        Ok(SharkMaintenance::new(dsn, &properties))
+    }
+
+    pub async fn set_device_operating_mode(&self, dsn: &str, mode: OperatingMode) -> Result<()> {
+        self.set_property(dsn, properties::SET_OPERATING_MODE, mode as u8)
+            .await
+    }
+
+    /// Pause the current clean, fails if the robot isn't cleaning
+    pub async fn pause(&self, dsn: &str) -> Result<()> {
+        match self.get_operating_mode(dsn).await? {
+            Some(OperatingMode::Start) => {
+                self.set_device_operating_mode(dsn, OperatingMode::Pause)
+                    .await
+            }
+            mode => Err(SharkError::InvalidState(format!(
+                "cannot pause, robot is in mode {:?}",
+                mode
+            ))),
+        }
+    }
+
+    /// Resume a paused clean, fails if the robot isn't paused
+    pub async fn resume(&self, dsn: &str) -> Result<()> {
+        match self.get_operating_mode(dsn).await? {
+            Some(OperatingMode::Pause) => {
+                self.set_device_operating_mode(dsn, OperatingMode::Start)
+                    .await
+            }
+            mode => Err(SharkError::InvalidState(format!(
+                "cannot resume, robot is in mode {:?}",
+                mode
+            ))),
+        }
+    }
+
+    pub async fn get_operating_mode(&self, dsn: &str) -> Result<Option<OperatingMode>> {
+        let mode: i64 = self.get_property(dsn, properties::OPERATING_MODE).await?;
+        Ok(OperatingMode::from_value(mode))
+    }
+
+    pub async fn set_power_mode(&self, dsn: &str, mode: PowerMode) -> Result<()> {
+        self.set_property(dsn, properties::SET_POWER_MODE, mode as u8)
+            .await
+    }
+
+    /// Make the robot beep so it can be found
+    pub async fn locate(&self, dsn: &str) -> Result<()> {
+        self.set_property(dsn, properties::SET_FIND_DEVICE, 1).await
+    }
+
+    /// Get the names of the rooms on the robot's map
+    pub async fn get_rooms(&self, dsn: &str) -> Result<Vec<String>> {
+        let rooms: String = self.get_property(dsn, properties::ROBOT_ROOM_LIST).await?;
+        Ok(properties::parse_room_list(&rooms))
+    }
+
+    /// Start cleaning only the given rooms. Room names are matched case-insensitively against
+    /// the rooms returned by `get_rooms`.
+    pub async fn clean_rooms<S: AsRef<str>>(&self, dsn: &str, rooms: &[S]) -> Result<()> {
+        let known = self.get_rooms(dsn).await?;
+        let mut areas = Vec::with_capacity(rooms.len());
+        for room in rooms {
+            let room = room.as_ref();
+            match known.iter().find(|k| k.eq_ignore_ascii_case(room)) {
+                Some(k) => areas.push(k.as_str()),
+                None => return Err(SharkError::UnknownRoom(room.to_string())),
+            }
+        }
+
+        let areas = json!({ "areas_to_clean": areas }).to_string();
+        self.set_property(dsn, properties::SET_AREAS_TO_CLEAN, areas)
+            .await?;
+        self.set_device_operating_mode(dsn, OperatingMode::Start)
+            .await
+    }
+
+    /// Poll the device's properties every `interval` and yield an event for each property that
+    /// changed since the previous poll. Polling errors are yielded without ending the stream.
+    pub fn watch<S: Into<String>>(
+        &self,
+        dsn: S,
+        interval: Duration,
+    ) -> impl Stream<Item = Result<SharkEvent>> + '_ {
+        struct State {
+            dsn: String,
+            last: Option<SharkDeviceProperties>,
+            pending: VecDeque<PropertyChange>,
+        }
+
+        let state = State {
+            dsn: dsn.into(),
+            last: None,
+            pending: VecDeque::new(),
+        };
+
+        stream::unfold(state, move |mut state| async move {
+            loop {
+                if let Some(change) = state.pending.pop_front() {
+                    let event = SharkEvent {
+                        dsn: state.dsn.clone(),
+                        change,
+                    };
+                    return Some((Ok(event), state));
+                }
+
+                if state.last.is_some() {
+                    tokio::time::sleep(interval).await;
+                }
+
+                match self.refresh_device_properties(&state.dsn).await {
+                    Ok(props) => {
+                        if let Some(last) = &state.last {
+                            state.pending.extend(watch::diff(last, &props));
+                        }
+                        state.last = Some(props);
+                    }
+                    Err(e) => {
+                        // Don't spin on a failing first poll
+                        if state.last.is_none() {
+                            tokio::time::sleep(interval).await;
+                        }
+                        return Some((Err(e), state));
+                    }
+                }
+            }
+        })
+    }
+
+    /// Refresh API token. Tokens are refreshed automatically before they expire so this is only
+    /// needed to force a refresh.
+    pub async fn refresh_token(&self) -> Result<()> {
         self.ayla.refresh_token().await?;
         Ok(())
     }
 
-    /// Sign out of the Shark API
-    pub async fn sign_out(self) -> Result<()> {
+    /// Sign out of the Shark API. Signing out again is a no-op, while any other request signs in
+    /// again.
+    pub async fn sign_out(&self) -> Result<()> {
         self.ayla.sign_out().await?;
         Ok(())
     }
 }
 
+/// Property names end up in request paths, so anything but Ayla's `[A-Za-z0-9_]+` names could
+/// change which URL is requested
+fn check_property_name(name: &str) -> Result<()> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(SharkError::InvalidPropertyName(name.to_string()));
+    }
+    Ok(())
+}
+
+fn parse_url(url: &str) -> Result<Url> {
+    Url::parse(url).map_err(|e| SharkError::InvalidUrl(format!("{}: {}", url, e)))
+}
+
 /// Check for a successful Shark API response or return a SharkError
 async fn get_api_response<T>(r: Response) -> Result<T>
 where
@@ -156,8 +704,7 @@ where
 {
     if !r.status().is_success() {
         return Err(error::SharkError::ApiError(
-            r.status(),
-            r.text().await.unwrap(),
+            error::ApiError::from_response(r).await,
         ));
     }
 
@@ -0,0 +1,79 @@
+use crate::error::SharkError;
+use crate::SharkDevice;
+
+/// How many similarly named devices are suggested when a name isn't found
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Find the DSN of the device identified by `name`, which is either its DSN or its user-assigned
+/// name. Both are compared ignoring case.
+pub fn find_device(devices: &[SharkDevice], name: &str) -> Result<String, SharkError> {
+    let wanted = name.trim().to_lowercase();
+
+    if let Some(device) = devices.iter().find(|d| d.dsn.to_lowercase() == wanted) {
+        return Ok(device.dsn.clone());
+    }
+
+    let matches: Vec<&SharkDevice> = devices
+        .iter()
+        .filter(|d| d.product_name.trim().to_lowercase() == wanted)
+        .collect();
+
+    match matches.as_slice() {
+        [device] => Ok(device.dsn.clone()),
+        [] => Err(SharkError::UnknownDevice {
+            name: name.to_string(),
+            suggestions: suggestions(devices, &wanted),
+        }),
+        _ => Err(SharkError::AmbiguousDevice {
+            name: name.to_string(),
+            dsns: matches.iter().map(|d| d.dsn.clone()).collect(),
+        }),
+    }
+}
+
+/// The names of the devices closest to `wanted`, or all of them if none are close
+fn suggestions(devices: &[SharkDevice], wanted: &str) -> Vec<String> {
+    let mut close: Vec<(usize, &str)> = devices
+        .iter()
+        .filter_map(|d| {
+            let name = d.product_name.trim();
+            let lower = name.to_lowercase();
+            let distance = edit_distance(&lower, wanted);
+            let similar = lower.contains(wanted)
+                || wanted.contains(lower.as_str())
+                || distance <= (wanted.chars().count() / 3).max(2);
+            similar.then_some((distance, name))
+        })
+        .collect();
+
+    if close.is_empty() {
+        return devices
+            .iter()
+            .map(|d| d.product_name.trim().to_string())
+            .collect();
+    }
+
+    close.sort();
+    close
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, name)| name.to_string())
+        .collect()
+}
+
+/// The Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != *cb);
+            cur[j + 1] = substitution.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+
+    prev[b.len()]
+}
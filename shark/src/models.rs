@@ -1,7 +1,39 @@
+use crate::properties::Datapoint;
 use serde::Deserialize;
 
 #[derive(Deserialize)]
 pub(crate) struct AylaLoginResponse {
     pub access_token: String,
     pub refresh_token: String,
+    /// Lifetime of the access token in seconds
+    pub expires_in: u64,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct AylaPropertyResponse {
+    pub property: AylaProperty,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct AylaDatapointResponse<T> {
+    pub datapoint: Datapoint<T>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct AylaLanResponse {
+    pub lanip: AylaLanKey,
+}
+
+/// The secret shared with a device that LAN mode session keys are derived from
+#[derive(Deserialize, Clone)]
+pub(crate) struct AylaLanKey {
+    pub lanip_key: String,
+    pub lanip_key_id: i64,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct AylaProperty {
+    pub name: String,
+    #[serde(default)]
+    pub value: serde_json::Value,
 }
@@ -0,0 +1,196 @@
+use crate::models::AylaProperty;
+use crate::{ConnectionStatus, OperatingMode, PowerMode, SharkDevice};
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub(crate) const BATTERY_CAPACITY: &str = "GET_Battery_Capacity";
+pub(crate) const CHARGING_STATUS: &str = "GET_Charging_Status";
+pub(crate) const ERROR_CODE: &str = "GET_Error_Code";
+pub(crate) const FILTER_LIFE: &str = "GET_Filter_Life";
+pub(crate) const MAIN_BRUSH_LIFE: &str = "GET_Main_Brush_Life";
+pub(crate) const MAIN_FW_VERSION: &str = "GET_Main_PCB_FW_Version";
+pub(crate) const NAV_FW_VERSION: &str = "GET_Nav_Module_FW_Version";
+pub(crate) const OPERATING_MODE: &str = "GET_Operating_Mode";
+pub(crate) const POWER_MODE: &str = "GET_Power_Mode";
+pub(crate) const RECHARGING_TO_RESUME: &str = "GET_Recharging_To_Resume";
+pub(crate) const ROBOT_ROOM_LIST: &str = "GET_Robot_Room_List";
+pub(crate) const RSSI: &str = "GET_RSSI";
+pub(crate) const SET_AREAS_TO_CLEAN: &str = "SET_Areas_To_Clean";
+pub(crate) const SET_FIND_DEVICE: &str = "SET_Find_Device";
+pub(crate) const SIDE_BRUSH_LIFE: &str = "GET_Side_Brush_Life";
+pub(crate) const SET_OPERATING_MODE: &str = "SET_Operating_Mode";
+pub(crate) const SET_POWER_MODE: &str = "SET_Power_Mode";
+
+/// A value reported for a property, as returned by `SharkClient::get_property_history`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+pub struct Datapoint<T> {
+    pub value: T,
+    /// When the robot reported the value
+    pub created_at: DateTime<Utc>,
+}
+
+/// A snapshot of the properties reported by a Shark robot.
+///
+/// Properties that are not understood by this crate are kept in `extra` keyed by their Ayla
+/// property name.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+pub struct SharkDeviceProperties {
+    /// Battery charge in percent
+    pub battery_capacity: Option<u8>,
+    pub operating_mode: Option<OperatingMode>,
+    pub power_mode: Option<PowerMode>,
+    /// Whether the robot is currently charging on its dock
+    pub charging_status: Option<bool>,
+    /// Last error reported by the robot, 0 means no error
+    pub error_code: Option<i64>,
+    pub rssi: Option<i64>,
+    pub firmware_version: Option<String>,
+    pub nav_firmware_version: Option<String>,
+    pub recharging_to_resume: Option<bool>,
+    /// Rooms known to the robot's map
+    pub rooms: Option<Vec<String>>,
+    /// Main brush roll life remaining in percent
+    pub main_brush_life: Option<u8>,
+    /// Side brush life remaining in percent
+    pub side_brush_life: Option<u8>,
+    /// Filter life remaining in percent
+    pub filter_life: Option<u8>,
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+impl SharkDeviceProperties {
+    pub(crate) fn from_properties(properties: Vec<AylaProperty>) -> Self {
+        let mut props = Self::default();
+
+        for p in properties {
+            let value = p.value;
+            match p.name.as_str() {
+                BATTERY_CAPACITY => props.battery_capacity = from_value(value),
+                CHARGING_STATUS => props.charging_status = bool_from_value(&value),
+                ERROR_CODE => props.error_code = from_value(value),
+                FILTER_LIFE => props.filter_life = from_value(value),
+                MAIN_BRUSH_LIFE => props.main_brush_life = from_value(value),
+                SIDE_BRUSH_LIFE => props.side_brush_life = from_value(value),
+                MAIN_FW_VERSION => props.firmware_version = from_value(value),
+                NAV_FW_VERSION => props.nav_firmware_version = from_value(value),
+                OPERATING_MODE => {
+                    props.operating_mode = value.as_i64().and_then(OperatingMode::from_value)
+                }
+                POWER_MODE => props.power_mode = value.as_i64().and_then(PowerMode::from_value),
+                RECHARGING_TO_RESUME => props.recharging_to_resume = bool_from_value(&value),
+                ROBOT_ROOM_LIST => props.rooms = value.as_str().map(parse_room_list),
+                RSSI => props.rssi = from_value(value),
+                _ => {
+                    props.extra.insert(p.name, value);
+                }
+            }
+        }
+
+        props
+    }
+}
+
+fn from_value<T: DeserializeOwned>(value: serde_json::Value) -> Option<T> {
+    serde_json::from_value(value).ok()
+}
+
+/// The room list is reported as the map id followed by each room name, separated by ':'
+pub(crate) fn parse_room_list(value: &str) -> Vec<String> {
+    value
+        .split(':')
+        .skip(1)
+        .filter(|r| !r.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Ayla reports boolean properties as integers
+fn bool_from_value(value: &serde_json::Value) -> Option<bool> {
+    match value {
+        serde_json::Value::Bool(b) => Some(*b),
+        v => v.as_i64().map(|i| i != 0),
+    }
+}
+
+/// A summary of a Shark robot's current state.
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct SharkDeviceStatus {
+    pub dsn: String,
+    /// Battery charge in percent
+    pub battery_level: Option<u8>,
+    pub operating_mode: Option<OperatingMode>,
+    pub charging: Option<bool>,
+    /// Last error reported by the robot, 0 means no error
+    pub error_code: Option<i64>,
+    pub connection_status: Option<ConnectionStatus>,
+}
+
+impl SharkDeviceStatus {
+    pub(crate) fn new(device: &SharkDevice, properties: &SharkDeviceProperties) -> Self {
+        Self {
+            dsn: device.dsn.clone(),
+            battery_level: properties.battery_capacity,
+            operating_mode: properties.operating_mode,
+            charging: properties.charging_status,
+            error_code: properties.error_code,
+            connection_status: device.connection_status,
+        }
+    }
+}
+
+/// Consumables with this much life or less remaining need attention
+const CONSUMABLE_ATTENTION_PERCENT: u8 = 10;
+
+/// Remaining life of a consumable part.
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct ConsumableStatus {
+    pub percent_remaining: Option<u8>,
+    /// The part should be cleaned or replaced soon
+    pub needs_attention: bool,
+}
+
+impl ConsumableStatus {
+    fn new(percent_remaining: Option<u8>) -> Self {
+        Self {
+            percent_remaining,
+            needs_attention: percent_remaining.is_some_and(|p| p <= CONSUMABLE_ATTENTION_PERCENT),
+        }
+    }
+}
+
+/// The state of a Shark robot's consumable parts.
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct SharkMaintenance {
+    pub dsn: String,
+    pub main_brush: ConsumableStatus,
+    pub side_brush: ConsumableStatus,
+    pub filter: ConsumableStatus,
+    /// Last error reported by the robot, 0 means no error
+    pub error_code: Option<i64>,
+    /// A consumable needs attention or the robot is reporting an error
+    pub needs_attention: bool,
+}
+
+impl SharkMaintenance {
+    pub(crate) fn new(dsn: &str, properties: &SharkDeviceProperties) -> Self {
+        let main_brush = ConsumableStatus::new(properties.main_brush_life);
+        let side_brush = ConsumableStatus::new(properties.side_brush_life);
+        let filter = ConsumableStatus::new(properties.filter_life);
+        let needs_attention = main_brush.needs_attention
+            || side_brush.needs_attention
+            || filter.needs_attention
+            || properties.error_code.is_some_and(|e| e != 0);
+
+        Self {
+            dsn: dsn.to_string(),
+            main_brush,
+            side_brush,
+            filter,
+            error_code: properties.error_code,
+            needs_attention,
+        }
+    }
+}
@@ -1,35 +1,62 @@
+use std::str::FromStr;
+
 #[non_exhaustive]
+#[derive(Debug, Clone)]
 pub enum Region {
     Us,
     Eu,
+    /// An Ayla field deployment not built into this crate
+    Custom {
+        user_url: String,
+        device_url: String,
+        app_id: String,
+        app_secret: String,
+    },
 }
 
 impl Region {
-    pub(crate) fn user_url(&self) -> &'static str {
-        match *self {
+    pub(crate) fn user_url(&self) -> &str {
+        match self {
             Self::Us => "https://user-field-39a9391a.aylanetworks.com",
             Self::Eu => "https://user-field-eu.aylanetworks.com",
+            Self::Custom { user_url, .. } => user_url,
         }
     }
 
-    pub(crate) fn device_url(&self) -> &'static str {
-        match *self {
+    pub(crate) fn device_url(&self) -> &str {
+        match self {
             Self::Us => "https://ads-field-39a9391a.aylanetworks.com",
             Self::Eu => "https://ads-eu.aylanetworks.com",
+            Self::Custom { device_url, .. } => device_url,
         }
     }
 
-    pub(crate) fn app_id(&self) -> &'static str {
-        match *self {
+    pub(crate) fn app_id(&self) -> &str {
+        match self {
             Self::Us => "Shark-Android-field-id",
             Self::Eu => "Shark-Android-EUField-Fw-id",
+            Self::Custom { app_id, .. } => app_id,
         }
     }
 
-    pub(crate) fn app_secret(&self) -> &'static str {
-        match *self {
+    pub(crate) fn app_secret(&self) -> &str {
+        match self {
             Self::Us => "Shark-Android-field-Wv43MbdXRM297HUHotqe6lU1n-w",
             Self::Eu => "Shark-Android-EUField-s-zTykblGJujGcSSTaJaeE4PESI",
+            Self::Custom { app_secret, .. } => app_secret,
+        }
+    }
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    /// Parse one of the built in region names, "us" or "eu"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "us" => Ok(Self::Us),
+            "eu" => Ok(Self::Eu),
+            _ => Err(format!("unknown shark region: {}", s)),
         }
     }
 }
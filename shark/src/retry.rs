@@ -0,0 +1,116 @@
+use rand::Rng;
+use reqwest::{header, Method, Response};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::{self, Instant};
+
+/// How failed requests to the Ayla cloud are retried.
+///
+/// Requests that were rejected with a 429 or never reached Ayla are always retried. Server errors
+/// and timeouts are only retried for idempotent requests, so a datapoint write is never applied
+/// twice. A 429 asking to wait longer than `max_backoff` is returned to the caller instead, and
+/// later requests fail with a 429 until that wait is over.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Maximum number of retries after the initial attempt
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for each subsequent retry
+    pub initial_backoff: Duration,
+    /// Upper bound on the delay between retries
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Never retry a request
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    /// Exponential backoff with jitter for the given retry attempt, starting at 0
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff);
+        // Pick a delay between half and all of the backoff so clients don't retry in lockstep
+        backoff.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+pub(crate) fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::PUT | Method::DELETE
+    )
+}
+
+/// The delay requested by a `Retry-After` header given in seconds
+pub(crate) fn retry_after(res: &Response) -> Option<Duration> {
+    res.headers()
+        .get(header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// Spaces requests out by a minimum interval and holds all requests back when Ayla asks us to
+/// slow down.
+pub(crate) struct RateLimiter {
+    min_interval: Duration,
+    next: Mutex<Instant>,
+    /// Requests fail instead of being sent until then
+    blocked_until: std::sync::Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(min_interval: Duration) -> Self {
+        Self {
+            min_interval,
+            next: Mutex::new(Instant::now()),
+            blocked_until: std::sync::Mutex::new(Instant::now()),
+        }
+    }
+
+    /// How much longer requests must not be sent, if they are blocked
+    pub(crate) fn blocked_for(&self) -> Option<Duration> {
+        let until = *self.blocked_until.lock().unwrap();
+        until
+            .checked_duration_since(Instant::now())
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Fail all requests for at least `delay`
+    pub(crate) fn block(&self, delay: Duration) {
+        let mut until = self.blocked_until.lock().unwrap();
+        *until = (*until).max(Instant::now() + delay);
+    }
+
+    /// Wait until another request is allowed
+    pub(crate) async fn acquire(&self) {
+        let mut next = self.next.lock().await;
+        time::sleep_until(*next).await;
+        *next = Instant::now() + self.min_interval;
+    }
+
+    /// Hold back all requests for at least `delay`
+    pub(crate) async fn defer(&self, delay: Duration) {
+        let mut next = self.next.lock().await;
+        *next = (*next).max(Instant::now() + delay);
+    }
+}
@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// Ayla session tokens that can be persisted between runs to avoid signing in again.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SharkTokens {
+    /// The account the tokens belong to
+    pub email: String,
+    pub access_token: String,
+    pub refresh_token: String,
+    pub expires_at: SystemTime,
+}
+
+/// Storage for Shark session tokens.
+///
+/// The client saves its tokens every time it signs in or refreshes them, and clears them when
+/// signing out.
+pub trait TokenStore: Send + Sync {
+    fn load(&self) -> io::Result<Option<SharkTokens>>;
+    fn save(&self, tokens: &SharkTokens) -> io::Result<()>;
+    fn clear(&self) -> io::Result<()>;
+}
+
+/// A `TokenStore` that keeps tokens in a JSON file readable only by the current user.
+pub struct FileTokenStore {
+    path: PathBuf,
+}
+
+impl FileTokenStore {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl TokenStore for FileTokenStore {
+    fn load(&self) -> io::Result<Option<SharkTokens>> {
+        let mut f = match File::open(&self.path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut buf: Vec<u8> = Vec::new();
+        f.read_to_end(&mut buf)?;
+
+        Ok(Some(serde_json::from_slice(&buf)?))
+    }
+
+    fn save(&self, tokens: &SharkTokens) -> io::Result<()> {
+        // Write to a temporary file first so a crash never leaves a truncated token file behind
+        let tmp = self.path.with_extension("tmp");
+        let mut opts = OpenOptions::new();
+        opts.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            opts.mode(0o600);
+        }
+
+        let mut f = opts.open(&tmp)?;
+        f.write_all(&serde_json::to_vec(tokens)?)?;
+        f.sync_all()?;
+        fs::rename(&tmp, &self.path)
+    }
+
+    fn clear(&self) -> io::Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
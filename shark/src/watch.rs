@@ -0,0 +1,84 @@
+use crate::{OperatingMode, PowerMode, SharkDeviceProperties};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// A change in one of a Shark robot's properties, observed by `SharkClient::watch`.
+#[derive(Serialize, Debug, Clone, JsonSchema)]
+pub struct SharkEvent {
+    pub dsn: String,
+    pub change: PropertyChange,
+}
+
+/// The new value of a property that changed between two polls.
+#[derive(Serialize, Debug, Clone, PartialEq, JsonSchema)]
+#[serde(tag = "property", content = "value", rename_all = "snake_case")]
+pub enum PropertyChange {
+    OperatingMode(Option<OperatingMode>),
+    PowerMode(Option<PowerMode>),
+    /// The robot docked (true) or left the dock (false)
+    Charging(Option<bool>),
+    BatteryCapacity(Option<u8>),
+    /// The robot reported a new error, 0 when the error is cleared
+    ErrorCode(Option<i64>),
+    /// A property not otherwise understood by this crate. A property the robot stopped reporting
+    /// is reported with a null value.
+    Other {
+        name: String,
+        value: serde_json::Value,
+    },
+}
+
+impl SharkEvent {
+    /// The robot returned to its dock
+    pub fn is_docked(&self) -> bool {
+        self.change == PropertyChange::Charging(Some(true))
+    }
+
+    /// The robot reported an error, e.g. because it is stuck
+    pub fn is_error(&self) -> bool {
+        matches!(self.change, PropertyChange::ErrorCode(Some(code)) if code != 0)
+    }
+}
+
+/// Collect the changes between two snapshots of a robot's properties
+pub(crate) fn diff(
+    old: &SharkDeviceProperties,
+    new: &SharkDeviceProperties,
+) -> Vec<PropertyChange> {
+    let mut changes = Vec::new();
+
+    if old.operating_mode != new.operating_mode {
+        changes.push(PropertyChange::OperatingMode(new.operating_mode));
+    }
+    if old.power_mode != new.power_mode {
+        changes.push(PropertyChange::PowerMode(new.power_mode));
+    }
+    if old.charging_status != new.charging_status {
+        changes.push(PropertyChange::Charging(new.charging_status));
+    }
+    if old.battery_capacity != new.battery_capacity {
+        changes.push(PropertyChange::BatteryCapacity(new.battery_capacity));
+    }
+    if old.error_code != new.error_code {
+        changes.push(PropertyChange::ErrorCode(new.error_code));
+    }
+
+    for (name, value) in &new.extra {
+        if old.extra.get(name) != Some(value) {
+            changes.push(PropertyChange::Other {
+                name: name.clone(),
+                value: value.clone(),
+            });
+        }
+    }
+    for name in old.extra.keys() {
+        if !new.extra.contains_key(name) {
+            changes.push(PropertyChange::Other {
+                name: name.clone(),
+                value: serde_json::Value::Null,
+            });
+        }
+    }
+
+    changes
+}
@@ -0,0 +1,794 @@
+mod common;
+
+use common::{MockAyla, DSN};
+use futures::StreamExt;
+use serde_json::json;
+use shark::{
+    Datapoint, FileTokenStore, OperatingMode, PowerMode, PropertyChange, RetryPolicy, SharkError,
+    SharkEvent, SharkTokens, TokenStore,
+};
+use std::time::{Duration, Instant, SystemTime};
+use wiremock::ResponseTemplate;
+
+#[tokio::test]
+async fn sign_in_and_list_devices() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.expect_devices("token-1", 1).await;
+
+    let client = ayla.client().await;
+    let devices = client.get_devices().await.unwrap();
+
+    assert_eq!(devices.len(), 1);
+    assert_eq!(devices[0].dsn, DSN);
+    assert_eq!(devices[0].oem_model, "RV1001AE");
+    assert!(devices[0].is_online());
+    assert_eq!(devices[0].lan_ip, Some("192.168.1.50".parse().unwrap()));
+    assert_eq!(
+        devices[0].connected_at.unwrap().to_rfc3339(),
+        "2022-01-01T12:00:00+00:00"
+    );
+    assert!(!devices[0].extra.contains_key("connection_status"));
+}
+
+#[tokio::test]
+async fn invalid_lan_ip() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    let mut device = common::device();
+    device["lan_ip"] = json!("192.168.1");
+    ayla.respond_next(
+        "/apiv1/devices",
+        ResponseTemplate::new(200).set_body_json(json!([{ "device": device }])),
+        1,
+    )
+    .await;
+
+    let client = ayla.client().await;
+    assert!(client.get_devices().await.is_err());
+}
+
+#[tokio::test]
+async fn sign_in_failure() {
+    let ayla = MockAyla::start().await;
+
+    assert!(ayla.builder().build().await.is_err());
+}
+
+#[tokio::test]
+async fn set_operating_mode() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.expect_datapoint("token-1", "SET_Operating_Mode", json!(2))
+        .await;
+
+    let client = ayla.client().await;
+    client
+        .set_device_operating_mode(DSN, OperatingMode::Start)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn pause_and_resume() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.respond_next(
+        &format!("/apiv1/dsns/{}/properties/GET_Operating_Mode", DSN),
+        ResponseTemplate::new(200).set_body_json(common::property("GET_Operating_Mode", json!(2))),
+        1,
+    )
+    .await;
+    ayla.expect_datapoint("token-1", "SET_Operating_Mode", json!(1))
+        .await;
+    ayla.expect_property("token-1", "GET_Operating_Mode", json!(1))
+        .await;
+    ayla.expect_datapoint("token-1", "SET_Operating_Mode", json!(2))
+        .await;
+
+    let client = ayla.client().await;
+    client.pause(DSN).await.unwrap();
+    client.resume(DSN).await.unwrap();
+}
+
+#[tokio::test]
+async fn resume_requires_pause() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.expect_property("token-1", "GET_Operating_Mode", json!(0))
+        .await;
+
+    let client = ayla.client().await;
+    let err = client.resume(DSN).await.unwrap_err();
+    assert!(matches!(err, SharkError::InvalidState(_)));
+}
+
+#[tokio::test]
+async fn clean_rooms() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.respond_next(
+        &format!("/apiv1/dsns/{}/properties/GET_Robot_Room_List", DSN),
+        ResponseTemplate::new(200).set_body_json(common::property(
+            "GET_Robot_Room_List",
+            json!("map-1:Kitchen:Living Room"),
+        )),
+        3,
+    )
+    .await;
+    ayla.expect_datapoint(
+        "token-1",
+        "SET_Areas_To_Clean",
+        json!(r#"{"areas_to_clean":["Kitchen","Living Room"]}"#),
+    )
+    .await;
+    ayla.expect_datapoint("token-1", "SET_Operating_Mode", json!(2))
+        .await;
+
+    let client = ayla.client().await;
+    // The map id leading the room list isn't a room
+    assert_eq!(
+        client.get_rooms(DSN).await.unwrap(),
+        vec!["Kitchen", "Living Room"]
+    );
+    client
+        .clean_rooms(DSN, &["kitchen", "LIVING ROOM"])
+        .await
+        .unwrap();
+    let err = client.clean_rooms(DSN, &["Garage"]).await.unwrap_err();
+    assert!(matches!(err, SharkError::UnknownRoom(room) if room == "Garage"));
+
+    let writes: Vec<_> = ayla
+        .server
+        .received_requests()
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|r| {
+            r.method == wiremock::http::Method::Post && r.url.path().ends_with("/datapoints")
+        })
+        .collect();
+    assert_eq!(writes.len(), 2);
+    assert!(writes[0].url.path().contains("/SET_Areas_To_Clean/"));
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(&writes[0].body).unwrap(),
+        json!({ "datapoint": {
+            "value": r#"{"areas_to_clean":["Kitchen","Living Room"]}"#
+        }})
+    );
+    assert!(writes[1].url.path().contains("/SET_Operating_Mode/"));
+}
+
+#[tokio::test]
+async fn set_power_mode() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.expect_datapoint("token-1", "SET_Power_Mode", json!(1))
+        .await;
+
+    let client = ayla.client().await;
+    client.set_power_mode(DSN, PowerMode::Eco).await.unwrap();
+}
+
+#[tokio::test]
+async fn locate() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.expect_datapoint("token-1", "SET_Find_Device", json!(1))
+        .await;
+
+    let client = ayla.client().await;
+    client.locate(DSN).await.unwrap();
+}
+
+#[tokio::test]
+async fn device_properties() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.expect_properties(
+        "token-1",
+        json!([
+            common::property("GET_Battery_Capacity", json!(87)),
+            common::property("GET_Operating_Mode", json!(3)),
+            common::property("GET_Power_Mode", json!(2)),
+            common::property("GET_Charging_Status", json!(0)),
+            common::property("GET_Robot_Room_List", json!("map1:Kitchen:Living Room")),
+            common::property("GET_Something_New", json!("hello")),
+        ]),
+    )
+    .await;
+
+    let client = ayla.client().await;
+    let props = client.get_device_properties(DSN).await.unwrap();
+
+    assert_eq!(props.battery_capacity, Some(87));
+    assert_eq!(props.operating_mode, Some(OperatingMode::Return));
+    assert_eq!(props.power_mode, Some(PowerMode::Max));
+    assert_eq!(props.charging_status, Some(false));
+    assert_eq!(
+        props.rooms,
+        Some(vec!["Kitchen".to_string(), "Living Room".to_string()])
+    );
+    assert_eq!(props.extra["GET_Something_New"], json!("hello"));
+}
+
+#[tokio::test]
+async fn maintenance() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.expect_properties(
+        "token-1",
+        json!([
+            common::property("GET_Main_Brush_Life", json!(80)),
+            common::property("GET_Side_Brush_Life", json!(5)),
+            common::property("GET_Filter_Life", json!(50)),
+            common::property("GET_Error_Code", json!(0)),
+        ]),
+    )
+    .await;
+
+    let client = ayla.client().await;
+    let maintenance = client.get_maintenance(DSN).await.unwrap();
+
+    assert_eq!(maintenance.main_brush.percent_remaining, Some(80));
+    assert!(!maintenance.main_brush.needs_attention);
+    assert!(maintenance.side_brush.needs_attention);
+    assert!(!maintenance.filter.needs_attention);
+    assert!(maintenance.needs_attention);
+}
+
+#[tokio::test]
+async fn explicit_refresh() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.expect_refresh("token-1", "token-2", 1).await;
+    ayla.expect_devices("token-2", 1).await;
+
+    let client = ayla.client().await;
+    client.refresh_token().await.unwrap();
+    client.get_devices().await.unwrap();
+}
+
+#[tokio::test]
+async fn refresh_before_expiry() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 60, 1).await;
+    ayla.expect_refresh("token-1", "token-2", 1).await;
+    ayla.expect_devices("token-2", 2).await;
+
+    let client = ayla.client().await;
+    client.get_devices().await.unwrap();
+    client.refresh_devices().await.unwrap();
+}
+
+#[tokio::test]
+async fn retry_after_unauthorized() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.expect_refresh("token-1", "token-2", 1).await;
+    // token-1 falls through to the mock's catch-all 401
+    ayla.expect_devices("token-2", 1).await;
+
+    let client = ayla.client().await;
+    client.get_devices().await.unwrap();
+}
+
+#[tokio::test]
+async fn sign_in_again_when_refresh_fails() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 60, 2).await;
+    ayla.reject_refresh("token-1").await;
+    ayla.expect_devices("token-1", 1).await;
+
+    let client = ayla.client().await;
+    client.get_devices().await.unwrap();
+}
+
+#[tokio::test]
+async fn sign_out() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.expect_sign_out("token-1").await;
+
+    let client = ayla.client().await;
+    client.sign_out().await.unwrap();
+    // Already signed out, so no second request is sent
+    client.sign_out().await.unwrap();
+}
+
+fn token_file(name: &str) -> std::path::PathBuf {
+    let path =
+        std::env::temp_dir().join(format!("shark-test-{}-{}.json", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+#[tokio::test]
+async fn token_store_saves_and_clears() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.expect_sign_out("token-1").await;
+
+    let path = token_file("save");
+    let client = ayla
+        .builder()
+        .token_store(FileTokenStore::new(&path))
+        .build()
+        .await
+        .unwrap();
+
+    let tokens = FileTokenStore::new(&path).load().unwrap().unwrap();
+    assert_eq!(tokens.email, common::EMAIL);
+    assert_eq!(tokens.access_token, "token-1");
+
+    client.sign_out().await.unwrap();
+    assert!(FileTokenStore::new(&path).load().unwrap().is_none());
+}
+
+#[tokio::test]
+async fn token_store_reuses_tokens() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 0).await;
+    ayla.expect_devices("stored", 1).await;
+
+    let path = token_file("reuse");
+    let store = FileTokenStore::new(&path);
+    store
+        .save(&SharkTokens {
+            email: common::EMAIL.to_string(),
+            access_token: "stored".to_string(),
+            refresh_token: common::refresh_token("stored"),
+            expires_at: SystemTime::now() + Duration::from_secs(86400),
+        })
+        .unwrap();
+
+    let client = ayla.builder().token_store(store).build().await.unwrap();
+    client.get_devices().await.unwrap();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn token_store_refreshes_expired_tokens() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 0).await;
+    ayla.expect_refresh("stored", "token-2", 1).await;
+    ayla.expect_devices("token-2", 1).await;
+
+    let path = token_file("expired");
+    let store = FileTokenStore::new(&path);
+    store
+        .save(&SharkTokens {
+            email: common::EMAIL.to_string(),
+            access_token: "stored".to_string(),
+            refresh_token: common::refresh_token("stored"),
+            expires_at: SystemTime::now(),
+        })
+        .unwrap();
+
+    let client = ayla.builder().token_store(store).build().await.unwrap();
+    client.get_devices().await.unwrap();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn structured_api_errors() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.expect_error(
+        "/apiv1/dsns/missing",
+        404,
+        json!({ "error": "Device not found" }),
+        1,
+    )
+    .await;
+    ayla.expect_error(
+        "/apiv1/dsns/busy",
+        503,
+        json!({ "errors": { "base": ["service unavailable"] } }),
+        3,
+    )
+    .await;
+
+    let client = ayla.client().await;
+
+    let err = client.get_device("missing").await.unwrap_err();
+    let api = err.api_error().unwrap();
+    assert_eq!(api.status, 404);
+    assert_eq!(api.message, "Device not found");
+    assert!(!err.is_retryable());
+    assert!(!err.is_auth_error());
+
+    let err = client.get_device("busy").await.unwrap_err();
+    assert_eq!(err.api_error().unwrap().message, "base service unavailable");
+    assert!(err.is_retryable());
+}
+
+#[tokio::test]
+async fn auth_errors() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.reject_refresh("token-1").await;
+
+    let client = ayla.client().await;
+    let err = client.refresh_token().await.unwrap_err();
+    assert!(err.is_auth_error());
+    assert!(!err.is_retryable());
+}
+
+#[tokio::test]
+async fn retry_server_errors() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.respond_next("/apiv1/devices", ResponseTemplate::new(503), 2)
+        .await;
+    ayla.expect_devices("token-1", 1).await;
+
+    let client = ayla.client().await;
+    client.get_devices().await.unwrap();
+}
+
+#[tokio::test]
+async fn invalid_property_name() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+
+    let client = ayla.client().await;
+    for name in ["../../devices", "GET_Battery?x=1", ""] {
+        let err = client
+            .get_property_history::<serde_json::Value>(DSN, name, None, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, SharkError::InvalidPropertyName(_)));
+        let err = client.get_property::<i64>(DSN, name).await.unwrap_err();
+        assert!(matches!(err, SharkError::InvalidPropertyName(_)));
+        let err = client.set_property(DSN, name, 1).await.unwrap_err();
+        assert!(matches!(err, SharkError::InvalidPropertyName(_)));
+    }
+}
+
+#[tokio::test]
+async fn request_timeout() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    // The initial attempt and both retries time out
+    ayla.respond_next(
+        "/apiv1/devices",
+        ResponseTemplate::new(200).set_delay(Duration::from_secs(5)),
+        3,
+    )
+    .await;
+
+    let client = ayla
+        .builder()
+        .timeout(Duration::from_millis(200))
+        .build()
+        .await
+        .unwrap();
+    let start = Instant::now();
+    let err = client.refresh_devices().await.unwrap_err();
+    assert!(err.is_retryable());
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn no_retry_for_datapoint_writes() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.respond_next(
+        &format!("/apiv1/dsns/{}/properties/SET_Power_Mode/datapoints", DSN),
+        ResponseTemplate::new(500),
+        1,
+    )
+    .await;
+
+    let client = ayla.client().await;
+    let err = client
+        .set_power_mode(DSN, PowerMode::Max)
+        .await
+        .unwrap_err();
+    assert_eq!(err.api_error().unwrap().status, 500);
+}
+
+#[tokio::test]
+async fn retry_after_rate_limited() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.respond_next(
+        &format!("/apiv1/dsns/{}/properties/SET_Power_Mode/datapoints", DSN),
+        ResponseTemplate::new(429).insert_header("Retry-After", "1"),
+        1,
+    )
+    .await;
+    ayla.expect_datapoint("token-1", "SET_Power_Mode", json!(2))
+        .await;
+
+    let client = ayla
+        .builder()
+        .retry_policy(RetryPolicy {
+            max_backoff: Duration::from_secs(2),
+            ..Default::default()
+        })
+        .build()
+        .await
+        .unwrap();
+    let start = Instant::now();
+    client.set_power_mode(DSN, PowerMode::Max).await.unwrap();
+    assert!(start.elapsed() >= Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn long_retry_after_is_returned() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.respond_next(
+        "/apiv1/devices",
+        ResponseTemplate::new(429).insert_header("Retry-After", "3600"),
+        1,
+    )
+    .await;
+
+    let client = ayla.client().await;
+    let start = Instant::now();
+    let err = client.get_devices().await.unwrap_err();
+    assert_eq!(err.api_error().unwrap().status, 429);
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    // Later requests aren't sent to Ayla until the wait is over
+    let err = client.refresh_devices().await.unwrap_err();
+    assert_eq!(err.api_error().unwrap().status, 429);
+    assert!(err.is_retryable());
+    let requests = ayla.server.received_requests().await.unwrap();
+    assert_eq!(
+        requests
+            .iter()
+            .filter(|r| r.url.path() == "/apiv1/devices")
+            .count(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn rate_limited_after_retries() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    // The initial attempt and both retries are rate limited
+    ayla.respond_next(
+        "/apiv1/devices",
+        ResponseTemplate::new(429).insert_header("Retry-After", "1"),
+        3,
+    )
+    .await;
+    ayla.expect_devices("token-1", 1).await;
+
+    let client = ayla
+        .builder()
+        .retry_policy(RetryPolicy {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_secs(2),
+        })
+        .build()
+        .await
+        .unwrap();
+    let err = client.refresh_devices().await.unwrap_err();
+    assert_eq!(err.api_error().unwrap().status, 429);
+
+    // The next request still waits as long as the last 429 asked
+    let start = Instant::now();
+    client.refresh_devices().await.unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(900));
+}
+
+#[tokio::test]
+async fn rate_limit() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.expect_devices("token-1", 2).await;
+
+    let client = ayla
+        .builder()
+        .rate_limit(Duration::from_millis(200))
+        .build()
+        .await
+        .unwrap();
+    let start = Instant::now();
+    client.refresh_devices().await.unwrap();
+    client.refresh_devices().await.unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(200));
+}
+
+#[tokio::test]
+async fn watch_property_changes() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.respond_next(
+        &format!("/apiv1/dsns/{}/properties", DSN),
+        ResponseTemplate::new(200).set_body_json(json!([
+            common::property("GET_Operating_Mode", json!(2)),
+            common::property("GET_Charging_Status", json!(0)),
+            common::property("GET_Battery_Capacity", json!(40)),
+            common::property("GET_Clean_Count", json!(7)),
+            common::property("GET_Dock_Status", json!(1)),
+        ])),
+        1,
+    )
+    .await;
+    ayla.expect_properties(
+        "token-1",
+        json!([
+            common::property("GET_Operating_Mode", json!(3)),
+            common::property("GET_Charging_Status", json!(1)),
+            common::property("GET_Battery_Capacity", json!(40)),
+            common::property("GET_Clean_Count", json!(8)),
+        ]),
+    )
+    .await;
+
+    let client = ayla.client().await;
+    let events: Vec<SharkEvent> = client
+        .watch(DSN, Duration::from_millis(10))
+        .take(4)
+        .map(Result::unwrap)
+        .collect()
+        .await;
+
+    assert_eq!(
+        events[0].change,
+        PropertyChange::OperatingMode(Some(OperatingMode::Return))
+    );
+    assert_eq!(events[1].change, PropertyChange::Charging(Some(true)));
+    assert!(events[1].is_docked());
+    assert_eq!(
+        events[2].change,
+        PropertyChange::Other {
+            name: "GET_Clean_Count".to_string(),
+            value: json!(8),
+        }
+    );
+    // A property that is no longer reported
+    assert_eq!(
+        events[3].change,
+        PropertyChange::Other {
+            name: "GET_Dock_Status".to_string(),
+            value: serde_json::Value::Null,
+        }
+    );
+}
+
+#[tokio::test]
+async fn resolve_device_by_name() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    // The second lookup is served from the cached list, the unknown name fetches it again
+    ayla.expect_devices("token-1", 2).await;
+
+    let client = ayla.client().await;
+    assert_eq!(client.resolve_device("sharkNADO").await.unwrap(), DSN);
+    assert_eq!(client.resolve_device(DSN).await.unwrap(), DSN);
+
+    match client.resolve_device("sharknad").await {
+        Err(SharkError::UnknownDevice { suggestions, .. }) => {
+            assert_eq!(suggestions, vec!["Sharknado".to_string()])
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+#[tokio::test]
+async fn resolve_ambiguous_device_name() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    let mut upstairs = common::device();
+    upstairs["dsn"] = json!("AC000W000000002");
+    ayla.respond_next(
+        "/apiv1/devices",
+        ResponseTemplate::new(200).set_body_json(json!([
+            { "device": common::device() },
+            { "device": upstairs },
+        ])),
+        1,
+    )
+    .await;
+
+    let client = ayla.client().await;
+    match client.resolve_device("Sharknado").await {
+        Err(SharkError::AmbiguousDevice { dsns, .. }) => {
+            assert_eq!(dsns, vec![DSN.to_string(), "AC000W000000002".to_string()])
+        }
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+#[tokio::test]
+async fn cached_devices() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.expect_devices("token-1", 2).await;
+
+    let client = ayla.client().await;
+    client.get_devices().await.unwrap();
+    client.get_devices().await.unwrap();
+    client.refresh_devices().await.unwrap();
+}
+
+#[tokio::test]
+async fn cache_disabled() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.expect_devices("token-1", 2).await;
+
+    let client = ayla
+        .builder()
+        .cache_ttl(Duration::ZERO)
+        .build()
+        .await
+        .unwrap();
+    client.get_devices().await.unwrap();
+    client.get_devices().await.unwrap();
+}
+
+#[tokio::test]
+async fn writes_invalidate_cached_properties() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.respond_next(
+        &format!("/apiv1/dsns/{}/properties", DSN),
+        ResponseTemplate::new(200)
+            .set_body_json(json!([common::property("GET_Power_Mode", json!(0))])),
+        2,
+    )
+    .await;
+    ayla.expect_datapoint("token-1", "SET_Power_Mode", json!(2))
+        .await;
+
+    let client = ayla.client().await;
+    client.get_device_properties(DSN).await.unwrap();
+    client.get_device_properties(DSN).await.unwrap();
+    client.set_power_mode(DSN, PowerMode::Max).await.unwrap();
+    client.get_device_properties(DSN).await.unwrap();
+}
+
+#[tokio::test]
+async fn property_history() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.expect_history(
+        "token-1",
+        "GET_Battery_Capacity",
+        &[
+            ("filter[created_at_since_date]", "2022-01-01T00:00:00Z"),
+            ("limit", "2"),
+        ],
+        json!([
+            { "datapoint": {
+                "value": 80,
+                "created_at": "2022-01-01T13:00:00Z",
+                "updated_at": "2022-01-01T13:00:00Z",
+                "echo": false,
+            }},
+            { "datapoint": {
+                "value": 95,
+                "created_at": "2022-01-01T12:00:00Z",
+                "updated_at": "2022-01-01T12:00:00Z",
+                "echo": false,
+            }},
+        ]),
+    )
+    .await;
+
+    let client = ayla.client().await;
+    let since = "2022-01-01T00:00:00Z".parse().unwrap();
+    let history: Vec<Datapoint<u8>> = client
+        .get_property_history(DSN, "GET_Battery_Capacity", Some(since), Some(2))
+        .await
+        .unwrap();
+
+    let values: Vec<u8> = history.iter().map(|d| d.value).collect();
+    assert_eq!(values, vec![95, 80]);
+    assert_eq!(
+        history[0].created_at,
+        "2022-01-01T12:00:00Z"
+            .parse::<chrono::DateTime<chrono::Utc>>()
+            .unwrap()
+    );
+}
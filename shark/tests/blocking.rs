@@ -0,0 +1,35 @@
+mod common;
+
+use common::{MockAyla, DSN, EMAIL, PASSWORD};
+use serde_json::json;
+use shark::blocking::SharkClient;
+use shark::{OperatingMode, RetryPolicy};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn blocking_client() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.expect_devices("token-1", 1).await;
+    ayla.expect_datapoint("token-1", "SET_Operating_Mode", json!(2))
+        .await;
+
+    let url = ayla.url();
+    // The blocking client runs its own runtime so it must be used outside of the test's
+    tokio::task::spawn_blocking(move || {
+        let client = SharkClient::builder(EMAIL, PASSWORD)
+            .user_url(url.clone())
+            .device_url(url)
+            .retry_policy(RetryPolicy::none())
+            .build()
+            .unwrap();
+
+        let devices = client.get_devices().unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(client.resolve_device("sharknado").unwrap(), DSN);
+        client
+            .set_device_operating_mode(DSN, OperatingMode::Start)
+            .unwrap();
+    })
+    .await
+    .unwrap();
+}
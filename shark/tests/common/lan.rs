@@ -0,0 +1,212 @@
+//! A robot speaking Ayla's LAN mode protocol on localhost.
+//!
+//! When a client registers, the robot exchanges keys with the client if it has no session yet
+//! and then fetches and decrypts all of the client's queued commands, recording the property
+//! updates they contain.
+
+use super::{LAN_KEY, LAN_KEY_ID};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use openssl::symm::{Cipher, Crypter, Mode};
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+struct Keys {
+    sign: Vec<u8>,
+    crypto: Vec<u8>,
+    iv: Vec<u8>,
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let key = PKey::hmac(key).unwrap();
+    let mut signer = Signer::new(MessageDigest::sha256(), &key).unwrap();
+    signer.update(data).unwrap();
+    signer.sign_to_vec().unwrap()
+}
+
+impl Keys {
+    fn derive(seed: &str) -> Self {
+        let key = |suffix: &str| {
+            let seed = format!("{}{}", seed, suffix);
+            let mut msg = hmac(LAN_KEY.as_bytes(), seed.as_bytes());
+            msg.extend_from_slice(seed.as_bytes());
+            hmac(LAN_KEY.as_bytes(), &msg)
+        };
+
+        Self {
+            sign: key("0"),
+            crypto: key("1"),
+            iv: key("2")[..16].to_vec(),
+        }
+    }
+
+    fn open(&mut self, message: &Value) -> Value {
+        let enc = openssl::base64::decode_block(message["enc"].as_str().unwrap()).unwrap();
+        let mut crypter = Crypter::new(
+            Cipher::aes_256_cbc(),
+            Mode::Decrypt,
+            &self.crypto,
+            Some(&self.iv),
+        )
+        .unwrap();
+        crypter.pad(false);
+        let mut plaintext = vec![0; enc.len() + 16];
+        let n = crypter.update(&enc, &mut plaintext).unwrap();
+        plaintext.truncate(n);
+        self.iv = enc[enc.len() - 16..].to_vec();
+
+        let end = plaintext.iter().rposition(|b| *b != 0).unwrap() + 1;
+        plaintext.truncate(end);
+        let sign = openssl::base64::decode_block(message["sign"].as_str().unwrap()).unwrap();
+        assert_eq!(hmac(&self.sign, &plaintext), sign, "bad signature");
+
+        serde_json::from_slice(&plaintext).unwrap()
+    }
+}
+
+#[derive(Default)]
+struct State {
+    /// Keys of messages from the client, once keys were exchanged
+    app: Option<Keys>,
+    received: Vec<(String, Value)>,
+    seq_nos: Vec<u64>,
+}
+
+pub struct SimulatedDevice {
+    pub addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+}
+
+impl SimulatedDevice {
+    pub async fn start() -> Self {
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let svc_state = Arc::clone(&state);
+        let make_svc = make_service_fn(move |_| {
+            let state = Arc::clone(&svc_state);
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let state = Arc::clone(&state);
+                    async move { Ok::<_, Infallible>(local_reg(state, req).await) }
+                }))
+            }
+        });
+        let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_svc);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        Self { addr, state }
+    }
+
+    /// Wait until `n` property updates were received from the client and return them. The
+    /// client only waits until the robot fetched a command, not until it was decrypted.
+    pub async fn received(&self, n: usize) -> Vec<(String, Value)> {
+        for _ in 0..200 {
+            let received = self.state.lock().unwrap().received.clone();
+            if received.len() >= n {
+                return received;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("robot did not receive {} property updates", n);
+    }
+
+    /// Sequence numbers of the messages received from the client so far
+    pub fn seq_nos(&self) -> Vec<u64> {
+        self.state.lock().unwrap().seq_nos.clone()
+    }
+}
+
+async fn local_reg(state: Arc<Mutex<State>>, req: Request<Body>) -> Response<Body> {
+    let method = req.method().clone();
+    assert_eq!(req.uri().path(), "/local_reg.json");
+    let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+    let reg: Value = serde_json::from_slice(&body).unwrap();
+    let reg = &reg["local_reg"];
+
+    let has_session = state.lock().unwrap().app.is_some();
+    let status = match method {
+        Method::POST => StatusCode::ACCEPTED,
+        Method::PUT if has_session => StatusCode::ACCEPTED,
+        _ => StatusCode::NOT_FOUND,
+    };
+
+    if status == StatusCode::ACCEPTED {
+        let base = format!(
+            "http://{}:{}{}",
+            reg["ip"].as_str().unwrap(),
+            reg["port"],
+            reg["uri"].as_str().unwrap()
+        );
+        tokio::spawn(sync(state, base, method == Method::POST));
+    }
+
+    let mut res = Response::new(Body::empty());
+    *res.status_mut() = status;
+    res
+}
+
+/// Connect back to the client like a robot does after being notified
+async fn sync(state: Arc<Mutex<State>>, base: String, key_exchange: bool) {
+    let http = reqwest::Client::new();
+
+    if key_exchange {
+        let random_1 = "0123456789abcdef";
+        let time_1 = 1_600_000_000_000u64;
+        let res: Value = http
+            .post(format!("{}/key_exchange.json", base))
+            .json(&json!({ "key_exchange": {
+                "ver": 1,
+                "random_1": random_1,
+                "time_1": time_1,
+                "proto": 1,
+                "key_id": LAN_KEY_ID,
+            }}))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        let seed = format!(
+            "{}{}{}{}",
+            random_1,
+            res["random_2"].as_str().unwrap(),
+            time_1,
+            res["time_2"]
+        );
+        state.lock().unwrap().app = Some(Keys::derive(&seed));
+    }
+
+    loop {
+        let res = http
+            .get(format!("{}/commands.json", base))
+            .send()
+            .await
+            .unwrap();
+        let more = res.status() == StatusCode::PARTIAL_CONTENT;
+        let message: Value = res.json().await.unwrap();
+
+        let mut device = state.lock().unwrap();
+        let command = device.app.as_mut().unwrap().open(&message);
+        device.seq_nos.push(command["seq_no"].as_u64().unwrap());
+        if let Some(properties) = command["data"]["properties"].as_array() {
+            for p in properties {
+                let p = &p["property"];
+                let name = p["name"].as_str().unwrap().to_string();
+                device.received.push((name, p["value"].clone()));
+            }
+        }
+
+        if !more {
+            break;
+        }
+    }
+}
@@ -0,0 +1,271 @@
+//! A mock of the Ayla user and device services used by the Shark API.
+//!
+//! Every endpoint requires the caller to register the expected interaction, so tests fail if the
+//! client makes unexpected requests or skips expected ones.
+
+#![allow(dead_code)]
+
+pub mod lan;
+
+use serde_json::{json, Value};
+use shark::{RetryPolicy, SharkClient, SharkClientBuilder, Url};
+use std::time::Duration;
+use wiremock::matchers::{body_partial_json, header, method, path, path_regex, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+pub const EMAIL: &str = "user@email.com";
+pub const PASSWORD: &str = "p@ssword";
+pub const DSN: &str = "AC000W000000001";
+pub const LAN_KEY: &str = "bGFuLWtleS1mb3ItdGVzdHM=";
+pub const LAN_KEY_ID: i64 = 4242;
+
+pub struct MockAyla {
+    pub server: MockServer,
+}
+
+impl MockAyla {
+    pub async fn start() -> Self {
+        let server = MockServer::start().await;
+
+        // Anything we don't explicitly handle is treated like Ayla treats a bad token
+        Mock::given(path_regex("^/apiv1/"))
+            .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+                "error": "Your access token is invalid or expired."
+            })))
+            .with_priority(u8::MAX)
+            .mount(&server)
+            .await;
+
+        Self { server }
+    }
+
+    pub fn url(&self) -> Url {
+        Url::parse(&self.server.uri()).unwrap()
+    }
+
+    /// A client builder pointed at the mock server for both the user and device services that
+    /// retries failed requests twice without waiting long
+    pub fn builder(&self) -> SharkClientBuilder {
+        SharkClient::builder(EMAIL, PASSWORD)
+            .user_url(self.url())
+            .device_url(self.url())
+            .retry_policy(RetryPolicy {
+                max_retries: 2,
+                initial_backoff: Duration::from_millis(1),
+                max_backoff: Duration::from_millis(5),
+            })
+    }
+
+    pub async fn client(&self) -> SharkClient {
+        self.builder()
+            .build()
+            .await
+            .expect("failed to build client")
+    }
+
+    pub async fn expect_sign_in(&self, access_token: &str, expires_in: u64, times: u64) {
+        Mock::given(method("POST"))
+            .and(path("/users/sign_in"))
+            .and(body_partial_json(json!({
+                "user": { "email": EMAIL, "password": PASSWORD }
+            })))
+            .respond_with(login_response(access_token, expires_in))
+            .expect(times)
+            .mount(&self.server)
+            .await;
+    }
+
+    pub async fn expect_refresh(&self, old_access_token: &str, access_token: &str, times: u64) {
+        Mock::given(method("POST"))
+            .and(path("/users/refresh_token"))
+            .and(body_partial_json(json!({
+                "user": { "refresh_token": refresh_token(old_access_token) }
+            })))
+            .respond_with(login_response(access_token, 86400))
+            .expect(times)
+            .mount(&self.server)
+            .await;
+    }
+
+    pub async fn reject_refresh(&self, old_access_token: &str) {
+        Mock::given(method("POST"))
+            .and(path("/users/refresh_token"))
+            .and(body_partial_json(json!({
+                "user": { "refresh_token": refresh_token(old_access_token) }
+            })))
+            .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+                "error": "Your refresh token is invalid or expired."
+            })))
+            .expect(1)
+            .mount(&self.server)
+            .await;
+    }
+
+    pub async fn expect_sign_out(&self, access_token: &str) {
+        Mock::given(method("POST"))
+            .and(path("/users/sign_out"))
+            .and(body_partial_json(json!({
+                "user": { "access_token": access_token }
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+            .expect(1)
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Answer the next `times` requests to `request_path` with `response`, taking priority over
+    /// any other expectations
+    pub async fn respond_next(&self, request_path: &str, response: ResponseTemplate, times: u64) {
+        Mock::given(path(request_path))
+            .respond_with(response)
+            .up_to_n_times(times)
+            .expect(times)
+            .with_priority(1)
+            .mount(&self.server)
+            .await;
+    }
+
+    pub async fn expect_devices(&self, access_token: &str, times: u64) {
+        Mock::given(method("GET"))
+            .and(path("/apiv1/devices"))
+            .and(header("authorization", access_token))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+                { "device": device() }
+            ])))
+            .expect(times)
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Report the device at `lan_ip` as LAN enabled and hand out its LAN key
+    pub async fn expect_lan(&self, access_token: &str, lan_ip: &str) {
+        let mut device = device();
+        device["lan_ip"] = json!(lan_ip);
+        device["lan_enabled"] = json!(true);
+        Mock::given(method("GET"))
+            .and(path(format!("/apiv1/dsns/{}", DSN)))
+            .and(header("authorization", access_token))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "device": device })))
+            .expect(1)
+            .mount(&self.server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/apiv1/dsns/{}/lan.json", DSN)))
+            .and(header("authorization", access_token))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "lanip": {
+                    "lanip_key": LAN_KEY,
+                    "lanip_key_id": LAN_KEY_ID,
+                    "keep_alive": 30,
+                    "status": "enable",
+                }
+            })))
+            .expect(1)
+            .mount(&self.server)
+            .await;
+    }
+
+    pub async fn expect_properties(&self, access_token: &str, properties: Value) {
+        Mock::given(method("GET"))
+            .and(path(format!("/apiv1/dsns/{}/properties", DSN)))
+            .and(header("authorization", access_token))
+            .respond_with(ResponseTemplate::new(200).set_body_json(properties))
+            .expect(1)
+            .mount(&self.server)
+            .await;
+    }
+
+    pub async fn expect_property(&self, access_token: &str, name: &str, value: Value) {
+        Mock::given(method("GET"))
+            .and(path(format!("/apiv1/dsns/{}/properties/{}", DSN, name)))
+            .and(header("authorization", access_token))
+            .respond_with(ResponseTemplate::new(200).set_body_json(property(name, value)))
+            .expect(1)
+            .mount(&self.server)
+            .await;
+    }
+
+    /// Return `datapoints` when the history of `property` is listed with the given query
+    pub async fn expect_history(
+        &self,
+        access_token: &str,
+        property: &str,
+        query: &[(&str, &str)],
+        datapoints: Value,
+    ) {
+        let mut mock = Mock::given(method("GET"))
+            .and(path(format!(
+                "/apiv1/dsns/{}/properties/{}/datapoints.json",
+                DSN, property
+            )))
+            .and(header("authorization", access_token));
+        for (name, value) in query {
+            mock = mock.and(query_param(*name, *value));
+        }
+        mock.respond_with(ResponseTemplate::new(200).set_body_json(datapoints))
+            .expect(1)
+            .mount(&self.server)
+            .await;
+    }
+
+    pub async fn expect_datapoint(&self, access_token: &str, property: &str, value: Value) {
+        Mock::given(method("POST"))
+            .and(path(format!(
+                "/apiv1/dsns/{}/properties/{}/datapoints",
+                DSN, property
+            )))
+            .and(header("authorization", access_token))
+            .and(body_partial_json(
+                json!({ "datapoint": { "value": value } }),
+            ))
+            .respond_with(ResponseTemplate::new(201).set_body_json(json!({
+                "datapoint": { "value": value }
+            })))
+            .expect(1)
+            .mount(&self.server)
+            .await;
+    }
+
+    pub async fn expect_error(&self, request_path: &str, status: u16, body: Value, times: u64) {
+        Mock::given(path(request_path))
+            .respond_with(ResponseTemplate::new(status).set_body_json(body))
+            .expect(times)
+            .mount(&self.server)
+            .await;
+    }
+}
+
+/// The mock derives refresh tokens from access tokens so tests only need to track one value
+pub fn refresh_token(access_token: &str) -> String {
+    format!("refresh-{}", access_token)
+}
+
+fn login_response(access_token: &str, expires_in: u64) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({
+        "access_token": access_token,
+        "refresh_token": refresh_token(access_token),
+        "expires_in": expires_in,
+        "role": "EndUser",
+        "role_tags": [],
+    }))
+}
+
+pub fn device() -> Value {
+    json!({
+        "product_name": "Sharknado",
+        "model": "AY001MRT1",
+        "dsn": DSN,
+        "oem_model": "RV1001AE",
+        "sw_version": "Linker-ADA-1.2.3",
+        "mac": "0123456789ab",
+        "lan_ip": "192.168.1.50",
+        "connected_at": "2022-01-01T12:00:00Z",
+        "key": 1234567,
+        "lan_enabled": false,
+        "connection_status": "Online",
+    })
+}
+
+pub fn property(name: &str, value: Value) -> Value {
+    json!({ "property": { "name": name, "base_type": "integer", "value": value } })
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use common::lan::SimulatedDevice;
+use common::{MockAyla, DSN};
+use serde_json::json;
+use shark::{LanConfig, LanStats, OperatingMode, PowerMode, SharkError, Transport};
+use std::time::Duration;
+use wiremock::ResponseTemplate;
+
+fn lan_config(device_port: u16) -> LanConfig {
+    LanConfig {
+        listen: "127.0.0.1:0".parse().unwrap(),
+        device_port,
+        timeout: Duration::from_secs(2),
+    }
+}
+
+#[tokio::test]
+async fn lan_mode_commands() {
+    let device = SimulatedDevice::start().await;
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.expect_lan("token-1", "127.0.0.1").await;
+
+    let client = ayla
+        .builder()
+        .lan(lan_config(device.addr.port()))
+        .build()
+        .await
+        .unwrap();
+    assert_eq!(client.transport(DSN), Transport::Cloud);
+    client.set_transport(DSN, Transport::Lan).await.unwrap();
+    assert_eq!(client.transport(DSN), Transport::Lan);
+
+    // No datapoints are expected by the cloud, so these only succeed over the LAN
+    client.set_power_mode(DSN, PowerMode::Max).await.unwrap();
+    client
+        .set_device_operating_mode(DSN, OperatingMode::Start)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        device.received(2).await,
+        vec![
+            ("SET_Power_Mode".to_string(), json!(2)),
+            ("SET_Operating_Mode".to_string(), json!(2)),
+        ]
+    );
+    assert_eq!(device.seq_nos(), vec![0, 1]);
+    assert_eq!(client.lan_stats(DSN), Some(LanStats::default()));
+}
+
+/// A port nothing is listening on
+fn unreachable_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+#[tokio::test]
+async fn lan_mode_falls_back_to_cloud() {
+    let port = unreachable_port();
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.expect_lan("token-1", "127.0.0.1").await;
+    ayla.expect_datapoint("token-1", "SET_Power_Mode", json!(1))
+        .await;
+
+    let client = ayla.builder().lan(lan_config(port)).build().await.unwrap();
+    client.set_transport(DSN, Transport::Lan).await.unwrap();
+    client.set_power_mode(DSN, PowerMode::Eco).await.unwrap();
+
+    let stats = client.lan_stats(DSN).unwrap();
+    assert_eq!(stats.fallbacks, 1);
+    assert!(stats.last_error.is_some());
+}
+
+#[tokio::test]
+async fn lan_mode_and_cloud_fail() {
+    let port = unreachable_port();
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+    ayla.expect_lan("token-1", "127.0.0.1").await;
+    ayla.respond_next(
+        &format!("/apiv1/dsns/{}/properties/SET_Power_Mode/datapoints", DSN),
+        ResponseTemplate::new(500),
+        1,
+    )
+    .await;
+
+    let client = ayla.builder().lan(lan_config(port)).build().await.unwrap();
+    client.set_transport(DSN, Transport::Lan).await.unwrap();
+    let err = client
+        .set_power_mode(DSN, PowerMode::Eco)
+        .await
+        .unwrap_err();
+    assert_eq!(err.api_error().unwrap().status, 500);
+
+    let stats = client.lan_stats(DSN).unwrap();
+    assert_eq!(stats.fallbacks, 1);
+    assert!(stats.last_error.is_some());
+}
+
+#[tokio::test]
+async fn lan_mode_requires_config() {
+    let ayla = MockAyla::start().await;
+    ayla.expect_sign_in("token-1", 86400, 1).await;
+
+    let client = ayla.client().await;
+    assert!(matches!(
+        client.set_transport(DSN, Transport::Lan).await,
+        Err(SharkError::LanError(_))
+    ));
+}